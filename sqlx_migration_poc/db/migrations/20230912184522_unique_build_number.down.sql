@@ -0,0 +1 @@
+alter table test_run drop constraint test_run_build_number_key;
@@ -0,0 +1 @@
+alter table test_run add constraint test_run_build_number_key unique (build_number);
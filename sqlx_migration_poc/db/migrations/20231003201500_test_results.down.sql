@@ -0,0 +1,3 @@
+drop table test_result;
+drop table test_case;
+drop table test_suite;
@@ -0,0 +1,24 @@
+create table test_suite (
+    test_suite_id uuid primary key default gen_random_uuid(),
+    name text not null unique
+);
+
+create table test_case (
+    test_case_id uuid primary key default gen_random_uuid(),
+    test_suite_id uuid not null references test_suite (test_suite_id) on delete cascade,
+    name text not null,
+    unique (test_suite_id, name)
+);
+
+create table test_result (
+    test_result_id uuid primary key default gen_random_uuid(),
+    test_run_id uuid not null references test_run (test_run_id) on delete cascade,
+    test_case_id uuid not null references test_case (test_case_id) on delete cascade,
+    status text not null check (status in ('passed', 'failed', 'skipped', 'error')),
+    duration_ms bigint not null default 0 check (duration_ms >= 0),
+    failure_message text,
+    retries integer not null default 0 check (retries >= 0),
+    unique (test_run_id, test_case_id)
+);
+
+create index test_result_test_case_id_idx on test_result (test_case_id);
@@ -0,0 +1,11 @@
+-- Only a SHA-256 hash of each token is stored; the token itself is shown once,
+-- when it is issued.
+create table api_token (
+    api_token_id uuid primary key default gen_random_uuid(),
+    name text not null,
+    token_hash bytea not null unique,
+    scopes text[] not null check (scopes <@ array['runs:read', 'runs:write', 'admin']),
+    created_at timestamptz not null default now(),
+    last_used_at timestamptz,
+    revoked_at timestamptz
+);
@@ -0,0 +1,4 @@
+drop trigger test_run_updated on test_run;
+drop trigger test_run_created on test_run;
+drop function record_test_run_event();
+drop table test_run_event;
@@ -0,0 +1,37 @@
+-- Every change to a test run is recorded as an event and announced on the
+-- 'test_run_events' channel with the id of the event. Listeners follow the
+-- channel to see changes as they happen, and read this table to catch up on
+-- the ones they missed. Events outlive the runs they are about.
+create table test_run_event (
+    event_id bigint generated always as identity primary key,
+    kind text not null check (kind in ('created', 'updated')),
+    occurred_at timestamptz not null default now(),
+    test_run_id uuid not null,
+    build_number text not null,
+    build_url text,
+    build_timestamp timestamptz not null
+);
+
+create function record_test_run_event() returns trigger
+language plpgsql as $$
+declare
+    new_event_id bigint;
+begin
+    insert into test_run_event (kind, test_run_id, build_number, build_url, build_timestamp)
+    values (
+        case tg_op when 'INSERT' then 'created' else 'updated' end,
+        new.test_run_id, new.build_number, new.build_url, new.build_timestamp
+    )
+    returning event_id into new_event_id;
+
+    perform pg_notify('test_run_events', new_event_id::text);
+    return null;
+end;
+$$;
+
+create trigger test_run_created after insert on test_run
+    for each row execute function record_test_run_event();
+
+create trigger test_run_updated after update on test_run
+    for each row when (old.* is distinct from new.*)
+    execute function record_test_run_event();
@@ -0,0 +1,55 @@
+-- Fails while test_run_archive still holds archived partitions, rather than
+-- dropping them along with it.
+drop schema test_run_archive;
+
+alter table test_result drop constraint test_result_test_run_id_fkey;
+alter table test_run rename to test_run_partitioned;
+alter table test_run_partitioned rename constraint test_run_pkey to test_run_partitioned_pkey;
+drop trigger test_run_updated on test_run_partitioned;
+drop trigger test_run_created on test_run_partitioned;
+drop trigger test_run_key_removed on test_run_partitioned;
+drop trigger test_run_key_synced on test_run_partitioned;
+
+create table test_run (
+    test_run_id uuid primary key default gen_random_uuid(),
+    build_number text not null,
+    build_url text,
+    build_timestamp timestamptz not null default now()
+);
+insert into test_run (test_run_id, build_number, build_url, build_timestamp)
+select test_run_id, build_number, build_url, build_timestamp from test_run_partitioned;
+
+drop table test_run_partitioned;
+drop table test_run_key;
+drop function remove_test_run_key();
+drop function sync_test_run_key();
+drop function create_test_run_partition(date);
+drop index test_run_event_test_run_id_idx;
+
+alter table test_run add constraint test_run_build_number_key unique (build_number);
+alter table test_result add constraint test_result_test_run_id_fkey
+    foreign key (test_run_id) references test_run (test_run_id) on delete cascade;
+
+create or replace function record_test_run_event() returns trigger
+language plpgsql as $$
+declare
+    new_event_id bigint;
+begin
+    insert into test_run_event (kind, test_run_id, build_number, build_url, build_timestamp)
+    values (
+        case tg_op when 'INSERT' then 'created' else 'updated' end,
+        new.test_run_id, new.build_number, new.build_url, new.build_timestamp
+    )
+    returning event_id into new_event_id;
+
+    perform pg_notify('test_run_events', new_event_id::text);
+    return null;
+end;
+$$;
+
+create trigger test_run_created after insert on test_run
+    for each row execute function record_test_run_event();
+
+create trigger test_run_updated after update on test_run
+    for each row when (old.* is distinct from new.*)
+    execute function record_test_run_event();
@@ -0,0 +1,139 @@
+-- Test runs are range partitioned by the month of their build_timestamp, so
+-- that old months can be dropped or archived a whole partition at a time.
+-- Partitions are named test_run_yYYYYmMM and cover a calendar month in UTC;
+-- runs outside every month that has a partition land in test_run_default.
+--
+-- Postgres only enforces uniqueness within a partition, so the keys that must
+-- hold across all of them live in test_run_key, one row per run, maintained
+-- by triggers on test_run. Test results refer to that table.
+alter table test_result drop constraint test_result_test_run_id_fkey;
+alter table test_run rename to test_run_unpartitioned;
+alter table test_run_unpartitioned rename constraint test_run_pkey to test_run_unpartitioned_pkey;
+alter table test_run_unpartitioned drop constraint test_run_build_number_key;
+drop trigger test_run_created on test_run_unpartitioned;
+drop trigger test_run_updated on test_run_unpartitioned;
+
+create table test_run_key (
+    test_run_id uuid primary key,
+    build_number text not null constraint test_run_build_number_key unique
+);
+
+create table test_run (
+    test_run_id uuid not null default gen_random_uuid()
+        references test_run_key (test_run_id) on delete cascade,
+    build_number text not null,
+    build_url text,
+    build_timestamp timestamptz not null default now(),
+    primary key (test_run_id, build_timestamp)
+) partition by range (build_timestamp);
+
+create index test_run_build_number_idx on test_run (build_number);
+create table test_run_default partition of test_run default;
+
+-- Creates the partition for the month containing `month` unless it exists,
+-- moving any of its runs out of the default partition, and returns its name.
+create function create_test_run_partition(month date) returns text
+language plpgsql as $$
+declare
+    partition text := format('test_run_y%sm%s', to_char(month, 'YYYY'), to_char(month, 'MM'));
+    lower_bound timestamptz := date_trunc('month', month::timestamp) at time zone 'UTC';
+    upper_bound timestamptz := (date_trunc('month', month::timestamp) + interval '1 month') at time zone 'UTC';
+begin
+    perform pg_advisory_xact_lock(hashtext('create_test_run_partition'));
+    if to_regclass(partition) is not null then
+        return partition;
+    end if;
+
+    lock table test_run_default in exclusive mode;
+    execute format('create table %I (like test_run including defaults)', partition);
+    execute format(
+        'with moved as (delete from test_run_default where build_timestamp >= %L and build_timestamp < %L returning *) '
+        'insert into %I select * from moved',
+        lower_bound, upper_bound, partition
+    );
+    execute format(
+        'alter table test_run attach partition %I for values from (%L) to (%L)',
+        partition, lower_bound, upper_bound
+    );
+    return partition;
+end;
+$$;
+
+select create_test_run_partition(month)
+from (
+    select distinct date_trunc('month', build_timestamp at time zone 'UTC')::date as month
+    from test_run_unpartitioned
+) months;
+
+insert into test_run_key (test_run_id, build_number)
+select test_run_id, build_number from test_run_unpartitioned;
+insert into test_run select * from test_run_unpartitioned;
+drop table test_run_unpartitioned;
+
+alter table test_result add constraint test_result_test_run_id_fkey
+    foreign key (test_run_id) references test_run_key (test_run_id) on delete cascade;
+
+create function sync_test_run_key() returns trigger
+language plpgsql as $$
+begin
+    insert into test_run_key (test_run_id, build_number)
+    values (new.test_run_id, new.build_number)
+    on conflict (test_run_id) do update set build_number = excluded.build_number;
+    return new;
+end;
+$$;
+
+-- An update that moves a run to another partition is carried out as a delete
+-- followed by an insert, so a run's key is only removed once, at commit, the
+-- run is gone from every partition. The test results go with it.
+create function remove_test_run_key() returns trigger
+language plpgsql as $$
+begin
+    delete from test_run_key k
+    where k.test_run_id = old.test_run_id
+        and not exists (select from test_run r where r.test_run_id = old.test_run_id);
+    return null;
+end;
+$$;
+
+create trigger test_run_key_synced before insert or update of test_run_id, build_number on test_run
+    for each row execute function sync_test_run_key();
+
+create constraint trigger test_run_key_removed after delete on test_run
+    deferrable initially deferred
+    for each row execute function remove_test_run_key();
+
+-- For the same reason the insert half of such a move is recorded as an update.
+create index test_run_event_test_run_id_idx on test_run_event (test_run_id);
+
+create or replace function record_test_run_event() returns trigger
+language plpgsql as $$
+declare
+    new_event_id bigint;
+begin
+    insert into test_run_event (kind, test_run_id, build_number, build_url, build_timestamp)
+    values (
+        case
+            when tg_op = 'UPDATE' then 'updated'
+            when exists (select from test_run_event e where e.test_run_id = new.test_run_id) then 'updated'
+            else 'created'
+        end,
+        new.test_run_id, new.build_number, new.build_url, new.build_timestamp
+    )
+    returning event_id into new_event_id;
+
+    perform pg_notify('test_run_events', new_event_id::text);
+    return null;
+end;
+$$;
+
+create trigger test_run_created after insert on test_run
+    for each row execute function record_test_run_event();
+
+create trigger test_run_updated after update on test_run
+    for each row when (old.* is distinct from new.*)
+    execute function record_test_run_event();
+
+-- Partitions archived by the retention task, along with the test results of
+-- their runs.
+create schema test_run_archive;
@@ -0,0 +1,2 @@
+drop index test_run_event_transaction_id_idx;
+alter table test_run_event drop column transaction_id;
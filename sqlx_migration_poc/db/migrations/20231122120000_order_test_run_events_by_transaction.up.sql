@@ -0,0 +1,10 @@
+-- Event ids are handed out when an event is recorded, not when it commits, so
+-- a long transaction can commit an event with a lower id than ones already
+-- streamed to clients, and clients resuming after their last id would never
+-- see it. Events are therefore published in the order of the transactions
+-- recording them: one is only streamed once every transaction older than its
+-- own has ended, when nothing can still appear before it.
+alter table test_run_event
+    add column transaction_id xid8 not null default pg_current_xact_id();
+
+create index test_run_event_transaction_id_idx on test_run_event (transaction_id, event_id);
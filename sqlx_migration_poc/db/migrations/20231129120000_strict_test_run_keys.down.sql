@@ -0,0 +1,9 @@
+create or replace function sync_test_run_key() returns trigger
+language plpgsql as $$
+begin
+    insert into test_run_key (test_run_id, build_number)
+    values (new.test_run_id, new.build_number)
+    on conflict (test_run_id) do update set build_number = excluded.build_number;
+    return new;
+end;
+$$;
@@ -0,0 +1,22 @@
+-- Inserting a run whose id already had a key used to overwrite that key, so a
+-- new run could take over the id, and the results, of another. Such inserts
+-- now fail, as they do on a taken build number. The exception is the insert
+-- half of an update that moves a run to another partition: by then the run is
+-- gone from its old partition, but its key is only removed at commit.
+create or replace function sync_test_run_key() returns trigger
+language plpgsql as $$
+begin
+    if tg_op = 'UPDATE' then
+        update test_run_key set test_run_id = new.test_run_id, build_number = new.build_number
+        where test_run_id = old.test_run_id;
+    elsif exists (select from test_run_key k where k.test_run_id = new.test_run_id)
+        and not exists (select from test_run r where r.test_run_id = new.test_run_id) then
+        update test_run_key set build_number = new.build_number
+        where test_run_id = new.test_run_id;
+    else
+        insert into test_run_key (test_run_id, build_number)
+        values (new.test_run_id, new.build_number);
+    end if;
+    return new;
+end;
+$$;
@@ -0,0 +1,8 @@
+create table test_run (
+    test_run_id blob primary key not null,
+    build_number text not null unique,
+    build_url text,
+    build_timestamp text not null
+);
+
+create index test_run_build_timestamp_idx on test_run (build_timestamp, test_run_id);
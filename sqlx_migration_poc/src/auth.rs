@@ -0,0 +1,89 @@
+//! Bearer token authentication of API requests.
+
+use crate::error::Error;
+use crate::schema::api_token::{ApiToken, Scope};
+use axum::extract::{Request, State};
+use axum::http::{header, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use sqlx::PgPool;
+use tracing::debug;
+
+/// The scope a request needs, or `None` for endpoints that stay open so that
+/// probes, scrapers and API clients work without a token.
+pub(crate) fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    match path {
+        "/healthz" | "/readyz" | "/metrics" | "/openapi.json" => None,
+        "/migrations" => Some(Scope::Admin),
+        // Only queries are served, so POSTing one just reads.
+        "/graphql" => Some(Scope::RunsRead),
+        _ if path.starts_with("/admin/") => Some(Scope::Admin),
+        _ if method == Method::GET || method == Method::HEAD => Some(Scope::RunsRead),
+        _ => Some(Scope::RunsWrite),
+    }
+}
+
+fn bearer_token(request: &Request) -> Result<&str, Error> {
+    let value = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .ok_or_else(|| Error::Unauthenticated("missing bearer token".to_string()))?;
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| Error::Unauthenticated("malformed authorization header".to_string()))
+}
+
+/// Middleware that lets a request through only if it carries a live token
+/// with the scope [`required_scope`] asks for. The token is made available to
+/// handlers as a request extension.
+pub(crate) async fn authenticate(
+    State(pool): State<&'static PgPool>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    let Some(scope) = required_scope(request.method(), request.uri().path()) else {
+        return Ok(next.run(request).await);
+    };
+
+    let api_token = ApiToken::authenticate(bearer_token(&request)?, pool)
+        .await?
+        .ok_or_else(|| Error::Unauthenticated("invalid or revoked token".to_string()))?;
+    if !api_token.scopes.contains(&scope) {
+        return Err(Error::Forbidden(format!("token lacks scope '{scope}'")));
+    }
+
+    debug!(token = %api_token.name, %scope, "Authenticated request");
+    request.extensions_mut().insert(api_token);
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_requests_to_scopes() {
+        let cases = [
+            (Method::GET, "/healthz", None),
+            (Method::GET, "/metrics", None),
+            (Method::GET, "/openapi.json", None),
+            (Method::GET, "/test-runs", Some(Scope::RunsRead)),
+            (Method::GET, "/test-runs/export", Some(Scope::RunsRead)),
+            (Method::POST, "/test-runs/import", Some(Scope::RunsWrite)),
+            (Method::POST, "/graphql", Some(Scope::RunsRead)),
+            (Method::DELETE, "/test-runs/1", Some(Scope::RunsWrite)),
+            (Method::GET, "/migrations", Some(Scope::Admin)),
+            (Method::POST, "/admin/tokens", Some(Scope::Admin)),
+            (Method::GET, "/no-such-route", Some(Scope::RunsRead)),
+        ];
+
+        for (method, path, scope) in cases {
+            assert_eq!(required_scope(&method, path), scope, "{method} {path}");
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Command line interface of the service binary.
+
+use crate::config::{Config, ConfigArgs, StorageBackend};
+use crate::db::{self, MIGRATOR};
+use crate::schema::api_token::{ApiToken, NewApiToken, Scope};
+use crate::schema::migration::MigrationStatus;
+use crate::start_server;
+use sqlx::PgPool;
+use std::process::ExitCode;
+use std::time::Duration;
+use tracing::info;
+use uuid::Uuid;
+
+/// Records CI test runs and their results and serves them over HTTP.
+#[derive(Debug, clap::Parser)]
+#[clap(version)]
+pub struct Cli {
+    #[clap(flatten)]
+    pub config: ConfigArgs,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (the default)
+    Serve,
+
+    /// Inspect or apply the database migrations embedded in this binary
+    #[clap(subcommand)]
+    Migrate(MigrateCommand),
+
+    /// Manage API tokens, e.g. to issue the first admin token
+    #[clap(subcommand)]
+    Token(TokenCommand),
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum MigrateCommand {
+    /// Apply all pending migrations
+    Up {
+        /// Print the SQL of the pending migrations instead of running it
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// List embedded and applied migrations
+    Status,
+
+    /// Revert the most recently applied migration
+    Revert {
+        /// Print the SQL that would revert the migration instead of running it
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Fail if applied migrations have drifted from the embedded ones
+    Check,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum TokenCommand {
+    /// Issue a token and print it; it cannot be shown again
+    Issue {
+        /// What the token is for
+        #[clap(long)]
+        name: String,
+
+        /// A scope to grant; repeat for several
+        #[clap(long = "scope", value_enum, required = true)]
+        scopes: Vec<Scope>,
+    },
+
+    /// Revoke a token
+    Revoke { api_token_id: Uuid },
+}
+
+pub async fn run(
+    command: Option<Command>,
+    config: &Config,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    match command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            start_server(config).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Migrate(_) if config.storage.backend != StorageBackend::Postgres => {
+            Err("The migrate subcommands only manage the Postgres schema".into())
+        }
+        Command::Token(_) if config.storage.backend != StorageBackend::Postgres => {
+            Err("API tokens are only stored on the Postgres backend".into())
+        }
+        Command::Migrate(command) => {
+            let pool = db::init_pool(&config.database).await?;
+            let lock_timeout = config.database.migrations.lock_timeout();
+            let result = migrate(command, &pool, lock_timeout).await;
+            pool.close().await;
+            result
+        }
+        Command::Token(command) => {
+            let pool = db::init_pool(&config.database).await?;
+            let result = token(command, &pool).await;
+            pool.close().await;
+            result
+        }
+    }
+}
+
+async fn token(
+    command: TokenCommand,
+    pool: &PgPool,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    match command {
+        TokenCommand::Issue { name, scopes } => {
+            let issued = ApiToken::issue(NewApiToken { name, scopes }, pool).await?;
+            eprintln!("Issued token {}", issued.api_token.api_token_id);
+            println!("{}", issued.token);
+        }
+        TokenCommand::Revoke { api_token_id } => {
+            ApiToken::revoke(api_token_id, pool).await?;
+            println!("Revoked token {api_token_id}");
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn migrate(
+    command: MigrateCommand,
+    pool: &PgPool,
+    lock_timeout: Duration,
+) -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let statuses = MigrationStatus::get_all(pool).await?;
+
+    match command {
+        MigrateCommand::Status => {
+            print_statuses(&statuses);
+            Ok(ExitCode::SUCCESS)
+        }
+        MigrateCommand::Check => {
+            if report_drift(&statuses) {
+                return Ok(ExitCode::FAILURE);
+            }
+            let pending = statuses.iter().filter(|s| !s.applied).count();
+            println!(
+                "No drift: {} migration(s) applied, {pending} pending",
+                statuses.len() - pending
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        MigrateCommand::Up { dry_run } => {
+            if report_drift(&statuses) {
+                return Ok(ExitCode::FAILURE);
+            }
+            let pending: Vec<_> = statuses.iter().filter(|s| !s.applied).collect();
+            if pending.is_empty() {
+                println!("Database is up to date");
+            } else if dry_run {
+                for status in pending {
+                    print_sql(status.version, false);
+                }
+            } else {
+                info!(count = pending.len(), "Applying migrations");
+                let applied = db::migrate(pool, lock_timeout).await?;
+                println!("Applied {applied} migration(s)");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        MigrateCommand::Revert { dry_run } => {
+            if report_drift(&statuses) {
+                return Ok(ExitCode::FAILURE);
+            }
+            let mut applied = statuses.iter().rev().filter(|s| s.applied);
+            let Some(last) = applied.next() else {
+                println!("No migrations have been applied");
+                return Ok(ExitCode::SUCCESS);
+            };
+            if !has_down_migration(last.version) {
+                eprintln!(
+                    "Migration {} {} cannot be reverted: it has no down script",
+                    last.version, last.description
+                );
+                return Ok(ExitCode::FAILURE);
+            }
+
+            if dry_run {
+                print_sql(last.version, true);
+            } else {
+                let target = applied.next().map_or(0, |s| s.version);
+                info!(version = last.version, "Reverting migration");
+                MIGRATOR.undo(pool, target).await?;
+                println!("Reverted {} {}", last.version, last.description);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+fn status_label(status: &MigrationStatus) -> &'static str {
+    if !status.applied {
+        "pending"
+    } else if !status.embedded {
+        "unknown"
+    } else if status.is_drifted() {
+        "drifted"
+    } else {
+        "applied"
+    }
+}
+
+fn print_statuses(statuses: &[MigrationStatus]) {
+    println!(
+        "{:<16} {:<8} {:<26} DESCRIPTION",
+        "VERSION", "STATUS", "INSTALLED ON"
+    );
+    for status in statuses {
+        let installed_on = status
+            .installed_on
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_default();
+        println!(
+            "{:<16} {:<8} {:<26} {}",
+            status.version,
+            status_label(status),
+            installed_on,
+            status.description
+        );
+    }
+}
+
+/// Prints every drifted migration and returns whether there were any.
+fn report_drift(statuses: &[MigrationStatus]) -> bool {
+    let drifted: Vec<_> = statuses.iter().filter(|s| s.is_drifted()).collect();
+    for status in &drifted {
+        let reason = if status.embedded {
+            "failed or was modified after it was applied"
+        } else {
+            "is applied but missing from this binary"
+        };
+        eprintln!(
+            "Migration {} {} {reason}",
+            status.version, status.description
+        );
+    }
+    !drifted.is_empty()
+}
+
+fn has_down_migration(version: i64) -> bool {
+    MIGRATOR
+        .iter()
+        .any(|m| m.version == version && m.migration_type.is_down_migration())
+}
+
+fn print_sql(version: i64, down: bool) {
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| m.version == version && m.migration_type.is_down_migration() == down)
+    {
+        println!("-- {} {}", migration.version, migration.description);
+        println!("{}", migration.sql.trim_end());
+        println!();
+    }
+}
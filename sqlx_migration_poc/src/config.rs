@@ -0,0 +1,983 @@
+//! Service configuration. Settings are layered, each layer overriding the
+//! previous one: built-in defaults, then an optional TOML file, then
+//! environment variables, then command line flags.
+
+use crate::error::Error;
+use serde::{Deserialize, Deserializer};
+use sqlx::postgres::PgSslMode;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::{env, fs};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub storage: StorageConfig,
+    pub auth: AuthConfig,
+    pub database: DatabaseConfig,
+    pub retention: RetentionConfig,
+    pub logging: LoggingConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+    /// How long in-flight requests may take to finish once a shutdown signal
+    /// has been received.
+    pub shutdown_timeout_secs: u64,
+    /// The largest test report `/test-runs/import` accepts.
+    pub max_report_size_mb: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            shutdown_timeout_secs: 30,
+            max_report_size_mb: 50,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    /// [`Self::max_report_size_mb`] in bytes.
+    pub fn max_report_size(&self) -> u64 {
+        self.max_report_size_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Database file of the SQLite backend, created if missing.
+    pub sqlite_path: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::default(),
+            sqlite_path: PathBuf::from("sqlx_migration_poc.db"),
+        }
+    }
+}
+
+/// API authentication. When enabled, every endpoint other than the health
+/// probes, `/metrics` and `/openapi.json` needs a bearer token with the right
+/// scope. Tokens are stored in Postgres, so this needs the Postgres backend.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub enabled: bool,
+}
+
+/// Where test runs are stored. Only Postgres supports test results, report
+/// imports and analytics; the other backends serve the test run endpoints.
+/// `memory` keeps nothing across restarts and is meant for demos.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Postgres,
+    Sqlite,
+    Memory,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub name: Option<String>,
+    #[serde(deserialize_with = "deserialize_ssl_mode")]
+    pub ssl_mode: Option<PgSslMode>,
+    pub pool: PoolConfig,
+    pub migrations: MigrationConfig,
+    pub replica: Option<ReplicaConfig>,
+}
+
+impl DatabaseConfig {
+    /// The settings to connect to the replica with, if one is configured.
+    /// Connection settings the replica leaves unset are the primary's.
+    pub fn replica(&self) -> Option<DatabaseConfig> {
+        let replica = self.replica.as_ref()?;
+        Some(DatabaseConfig {
+            host: replica.host.clone(),
+            port: replica.port.or(self.port),
+            username: replica.username.clone().or_else(|| self.username.clone()),
+            password: replica.password.clone().or_else(|| self.password.clone()),
+            name: replica.name.clone().or_else(|| self.name.clone()),
+            ssl_mode: replica.ssl_mode.or(self.ssl_mode),
+            pool: replica.pool.clone(),
+            migrations: MigrationConfig {
+                auto_migrate: false,
+                ..self.migrations.clone()
+            },
+            replica: None,
+        })
+    }
+}
+
+/// A read replica that read-only queries are sent to while it is healthy,
+/// falling back to the primary otherwise. A replica that is not streaming WAL
+/// from the primary is never healthy. Reads may not yet see the latest
+/// writes, by up to `max_lag_secs` when that is set.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplicaConfig {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub name: Option<String>,
+    #[serde(deserialize_with = "deserialize_ssl_mode")]
+    pub ssl_mode: Option<PgSslMode>,
+    pub pool: PoolConfig,
+    /// How often the replica is checked, and so how soon reads move off it
+    /// once it fails and back once it recovers.
+    pub health_check_interval_secs: u64,
+    /// The replica counts as unhealthy while it is further behind the
+    /// primary than this.
+    pub max_lag_secs: Option<u64>,
+}
+
+impl Default for ReplicaConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: None,
+            username: None,
+            password: None,
+            name: None,
+            ssl_mode: None,
+            pool: PoolConfig::default(),
+            health_check_interval_secs: 5,
+            max_lag_secs: None,
+        }
+    }
+}
+
+impl ReplicaConfig {
+    pub fn health_check_interval(&self) -> Duration {
+        Duration::from_secs(self.health_check_interval_secs)
+    }
+
+    pub fn max_lag(&self) -> Option<Duration> {
+        self.max_lag_secs.map(Duration::from_secs)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub min_connections: u32,
+    pub max_connections: u32,
+    pub acquire_timeout_secs: u64,
+    /// Idle connections are closed after this long; `0` keeps them forever.
+    pub idle_timeout_secs: u64,
+    /// Server-side `statement_timeout` for every connection; `0` disables it.
+    pub statement_timeout_ms: u64,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            min_connections: 0,
+            max_connections: 20,
+            acquire_timeout_secs: 30,
+            idle_timeout_secs: 600,
+            statement_timeout_ms: 0,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn acquire_timeout(&self) -> Duration {
+        Duration::from_secs(self.acquire_timeout_secs)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout_secs > 0).then(|| Duration::from_secs(self.idle_timeout_secs))
+    }
+
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        (self.statement_timeout_ms > 0).then(|| Duration::from_millis(self.statement_timeout_ms))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MigrationConfig {
+    /// Apply pending migrations when the server starts.
+    pub auto_migrate: bool,
+    /// How long to wait for another instance that is already migrating.
+    pub lock_timeout_secs: u64,
+}
+
+impl Default for MigrationConfig {
+    fn default() -> Self {
+        Self {
+            auto_migrate: true,
+            lock_timeout_secs: 60,
+        }
+    }
+}
+
+impl MigrationConfig {
+    pub fn lock_timeout(&self) -> Duration {
+        Duration::from_secs(self.lock_timeout_secs)
+    }
+}
+
+/// Removal of old test runs from Postgres. Runs are removed a monthly
+/// partition at a time, once the whole month is older than `max_age_days`,
+/// so they may be kept up to a month longer than that. Runs in the default
+/// partition, which holds those outside every month that has a partition, are
+/// removed one by one.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Keep test runs forever when unset.
+    pub max_age_days: Option<u32>,
+    pub action: RetentionAction,
+    /// How often partitions are created ahead of time and old ones removed.
+    pub interval_secs: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: None,
+            action: RetentionAction::default(),
+            interval_secs: 3600,
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn max_age(&self) -> Option<chrono::Duration> {
+        self.max_age_days
+            .map(|days| chrono::Duration::days(i64::from(days)))
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// What happens to test runs, and their results and events, once they are
+/// too old: `drop` deletes them, `archive` moves them to tables in the
+/// `test_run_archive` schema.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    #[default]
+    Drop,
+    Archive,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// The default level, or a list of `tracing` filter directives such as
+    /// `info,sqlx=warn`. `RUST_LOG`, when set, replaces this and `targets`.
+    pub level: String,
+    /// Level overrides for individual targets, e.g. `sqlx = "warn"`.
+    pub targets: BTreeMap<String, String>,
+    /// Write logs to a rolling file instead of stdout.
+    pub file: Option<LogFileConfig>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            level: "error".to_string(),
+            targets: BTreeMap::new(),
+            file: None,
+        }
+    }
+}
+
+impl LoggingConfig {
+    /// Builds the filter selecting which events get logged.
+    pub fn env_filter(&self) -> Result<EnvFilter, Error> {
+        let directives = match env::var("RUST_LOG") {
+            Ok(rust_log) if !rust_log.is_empty() => rust_log,
+            _ => {
+                let mut directives = vec![self.level.clone()];
+                directives.extend(
+                    self.targets
+                        .iter()
+                        .map(|(target, level)| format!("{target}={level}")),
+                );
+                directives.join(",")
+            }
+        };
+
+        EnvFilter::builder()
+            .parse(&directives)
+            .map_err(|e| Error::Config(vec![format!("invalid log level '{directives}': {e}")]))
+    }
+}
+
+/// A log file that is rotated daily and/or once it reaches a size, keeping at
+/// most `max_files` old files next to it.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub rotate_daily: bool,
+    pub max_size_mb: Option<u64>,
+    pub max_files: usize,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("sqlx_migration_poc.log"),
+            rotate_daily: true,
+            max_size_mb: None,
+            max_files: 7,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Json,
+    Compact,
+    Pretty,
+}
+
+/// `LOGGER=Default` predates `LOG_FORMAT` and selected compact logs. Any other
+/// value is rejected rather than silently ignored.
+fn parse_legacy_logger(s: &str) -> Result<LogFormat, String> {
+    match s {
+        "Default" => Ok(LogFormat::Compact),
+        _ => Err(format!(
+            "unknown logger '{s}'; set LOG_FORMAT to json, compact or pretty instead"
+        )),
+    }
+}
+
+fn parse_ssl_mode(s: &str) -> Result<PgSslMode, String> {
+    PgSslMode::from_str(s).map_err(|_| format!("invalid SSL mode '{s}'"))
+}
+
+fn deserialize_ssl_mode<'de, D>(deserializer: D) -> Result<Option<PgSslMode>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| parse_ssl_mode(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Identifies this process in logs and in `pg_stat_activity`: the
+/// `INSTANCE_ID` environment variable if set, otherwise `<hostname>-<pid>`.
+pub fn instance_id() -> &'static str {
+    static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+    INSTANCE_ID.get_or_init(|| {
+        env::var("INSTANCE_ID").unwrap_or_else(|_| {
+            let hostname = env::var("HOSTNAME")
+                .or_else(|_| fs::read_to_string("/etc/hostname"))
+                .map(|hostname| hostname.trim().to_string())
+                .unwrap_or_else(|_| "unknown".to_string());
+            format!("{hostname}-{}", std::process::id())
+        })
+    })
+}
+
+/// Settings that can be given on the command line or through the environment.
+/// Flags take precedence over environment variables, and both take precedence
+/// over the configuration file.
+#[derive(Clone, Debug, Default, clap::Parser)]
+pub struct ConfigArgs {
+    /// Path to a TOML configuration file
+    #[clap(long, env = "CONFIG_FILE", value_parser)]
+    pub config: Option<PathBuf>,
+
+    /// Address the HTTP server listens on
+    #[clap(long, env = "BIND_ADDRESS", value_parser)]
+    pub bind_address: Option<SocketAddr>,
+
+    /// Seconds to wait for in-flight requests when shutting down
+    #[clap(long, env = "SHUTDOWN_TIMEOUT_SECS", value_parser)]
+    pub shutdown_timeout_secs: Option<u64>,
+
+    /// Largest test report accepted for import, in megabytes
+    #[clap(long, env = "MAX_REPORT_SIZE_MB", value_parser)]
+    pub max_report_size_mb: Option<u64>,
+
+    /// Storage backend for test runs
+    #[clap(long, env = "STORAGE", value_enum)]
+    pub storage: Option<StorageBackend>,
+
+    #[clap(long, env = "SQLITE_PATH", value_parser)]
+    pub sqlite_path: Option<PathBuf>,
+
+    /// Whether requests need a bearer token
+    #[clap(long, env = "AUTH_ENABLED", value_parser)]
+    pub auth_enabled: Option<bool>,
+
+    #[clap(long, env = "DB_HOST", value_parser)]
+    pub db_host: Option<String>,
+
+    #[clap(long, env = "DB_PORT", value_parser)]
+    pub db_port: Option<u16>,
+
+    #[clap(long, env = "DB_USERNAME", value_parser)]
+    pub db_username: Option<String>,
+
+    #[clap(long, env = "DB_PASSWORD", value_parser, hide_env_values = true)]
+    pub db_password: Option<String>,
+
+    #[clap(long, env = "DB_NAME", value_parser)]
+    pub db_name: Option<String>,
+
+    #[clap(long, env = "DB_SSL_MODE", value_parser = parse_ssl_mode)]
+    pub db_ssl_mode: Option<PgSslMode>,
+
+    #[clap(long, env = "DB_POOL_MIN_CONNECTIONS", value_parser)]
+    pub db_pool_min_connections: Option<u32>,
+
+    #[clap(long, env = "DB_POOL_MAX_CONNECTIONS", value_parser)]
+    pub db_pool_max_connections: Option<u32>,
+
+    #[clap(long, env = "DB_ACQUIRE_TIMEOUT_SECS", value_parser)]
+    pub db_acquire_timeout_secs: Option<u64>,
+
+    #[clap(long, env = "DB_IDLE_TIMEOUT_SECS", value_parser)]
+    pub db_idle_timeout_secs: Option<u64>,
+
+    #[clap(long, env = "DB_STATEMENT_TIMEOUT_MS", value_parser)]
+    pub db_statement_timeout_ms: Option<u64>,
+
+    /// Whether to apply pending migrations when the server starts
+    #[clap(long, env = "DB_AUTO_MIGRATE", value_parser)]
+    pub db_auto_migrate: Option<bool>,
+
+    #[clap(long, env = "DB_MIGRATION_LOCK_TIMEOUT_SECS", value_parser)]
+    pub db_migration_lock_timeout_secs: Option<u64>,
+
+    /// Host of a read replica to send read-only queries to
+    #[clap(long, env = "DB_REPLICA_HOST", value_parser)]
+    pub db_replica_host: Option<String>,
+
+    #[clap(long, env = "DB_REPLICA_PORT", value_parser)]
+    pub db_replica_port: Option<u16>,
+
+    #[clap(long, env = "DB_REPLICA_HEALTH_CHECK_INTERVAL_SECS", value_parser)]
+    pub db_replica_health_check_interval_secs: Option<u64>,
+
+    /// Stop reading from the replica while it lags further behind than this
+    #[clap(long, env = "DB_REPLICA_MAX_LAG_SECS", value_parser)]
+    pub db_replica_max_lag_secs: Option<u64>,
+
+    /// Remove test runs built more than this many days ago
+    #[clap(long, env = "RETENTION_MAX_AGE_DAYS", value_parser)]
+    pub retention_max_age_days: Option<u32>,
+
+    /// Whether old test runs are dropped or archived
+    #[clap(long, env = "RETENTION_ACTION", value_enum)]
+    pub retention_action: Option<RetentionAction>,
+
+    #[clap(long, env = "RETENTION_INTERVAL_SECS", value_parser)]
+    pub retention_interval_secs: Option<u64>,
+
+    #[clap(long, env = "LOG_FORMAT", value_enum)]
+    pub log_format: Option<LogFormat>,
+
+    #[clap(long, env = "LOGGER", hide = true, value_parser = parse_legacy_logger)]
+    pub logger: Option<LogFormat>,
+
+    /// Default log level or filter directives, e.g. `info,sqlx=warn`
+    #[clap(long, env = "LOG_LEVEL", value_parser)]
+    pub log_level: Option<String>,
+
+    /// Write logs to this rolling file instead of stdout
+    #[clap(long, env = "LOG_FILE", value_parser)]
+    pub log_file: Option<PathBuf>,
+
+    #[clap(long, env = "LOG_FILE_ROTATE_DAILY", value_parser)]
+    pub log_file_rotate_daily: Option<bool>,
+
+    #[clap(long, env = "LOG_FILE_MAX_SIZE_MB", value_parser)]
+    pub log_file_max_size_mb: Option<u64>,
+
+    #[clap(long, env = "LOG_FILE_MAX_FILES", value_parser)]
+    pub log_file_max_files: Option<usize>,
+}
+
+impl ConfigArgs {
+    /// Reads the settings from environment variables only.
+    pub fn from_env() -> Result<Self, Error> {
+        <Self as clap::Parser>::try_parse_from([env!("CARGO_PKG_NAME")])
+            .map_err(|e| Error::Config(vec![e.to_string()]))
+    }
+
+    fn apply(&self, config: &mut Config) {
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *target = value.clone();
+            }
+        }
+
+        fn set_some<T: Clone>(target: &mut Option<T>, value: &Option<T>) {
+            if value.is_some() {
+                target.clone_from(value);
+            }
+        }
+
+        set(&mut config.server.bind_address, &self.bind_address);
+        set(
+            &mut config.server.shutdown_timeout_secs,
+            &self.shutdown_timeout_secs,
+        );
+        set(
+            &mut config.server.max_report_size_mb,
+            &self.max_report_size_mb,
+        );
+
+        set(&mut config.storage.backend, &self.storage);
+        set(&mut config.storage.sqlite_path, &self.sqlite_path);
+
+        set(&mut config.auth.enabled, &self.auth_enabled);
+
+        let database = &mut config.database;
+        set_some(&mut database.host, &self.db_host);
+        set_some(&mut database.port, &self.db_port);
+        set_some(&mut database.username, &self.db_username);
+        set_some(&mut database.password, &self.db_password);
+        set_some(&mut database.name, &self.db_name);
+        set_some(&mut database.ssl_mode, &self.db_ssl_mode);
+
+        let pool = &mut database.pool;
+        set(&mut pool.min_connections, &self.db_pool_min_connections);
+        set(&mut pool.max_connections, &self.db_pool_max_connections);
+        set(
+            &mut pool.acquire_timeout_secs,
+            &self.db_acquire_timeout_secs,
+        );
+        set(&mut pool.idle_timeout_secs, &self.db_idle_timeout_secs);
+        set(
+            &mut pool.statement_timeout_ms,
+            &self.db_statement_timeout_ms,
+        );
+
+        let migrations = &mut database.migrations;
+        set(&mut migrations.auto_migrate, &self.db_auto_migrate);
+        set(
+            &mut migrations.lock_timeout_secs,
+            &self.db_migration_lock_timeout_secs,
+        );
+
+        if let Some(host) = &self.db_replica_host {
+            database
+                .replica
+                .get_or_insert_with(ReplicaConfig::default)
+                .host = Some(host.clone());
+        }
+        if let Some(replica) = &mut database.replica {
+            set_some(&mut replica.port, &self.db_replica_port);
+            set(
+                &mut replica.health_check_interval_secs,
+                &self.db_replica_health_check_interval_secs,
+            );
+            set_some(&mut replica.max_lag_secs, &self.db_replica_max_lag_secs);
+        }
+
+        let retention = &mut config.retention;
+        set_some(&mut retention.max_age_days, &self.retention_max_age_days);
+        set(&mut retention.action, &self.retention_action);
+        set(&mut retention.interval_secs, &self.retention_interval_secs);
+
+        let logging = &mut config.logging;
+        set(&mut logging.format, &self.logger);
+        set(&mut logging.format, &self.log_format);
+        set(&mut logging.level, &self.log_level);
+        if let Some(path) = &self.log_file {
+            logging.file.get_or_insert_with(LogFileConfig::default).path = path.clone();
+        }
+        if let Some(file) = &mut logging.file {
+            set(&mut file.rotate_daily, &self.log_file_rotate_daily);
+            set_some(&mut file.max_size_mb, &self.log_file_max_size_mb);
+            set(&mut file.max_files, &self.log_file_max_files);
+        }
+    }
+}
+
+fn validate_pool(key: &str, pool: &PoolConfig, errors: &mut Vec<String>) {
+    if pool.max_connections == 0 {
+        errors.push(format!("{key}.max_connections must be at least 1"));
+    }
+    if pool.min_connections > pool.max_connections {
+        errors.push(format!(
+            "{key}.min_connections ({}) must not exceed max_connections ({})",
+            pool.min_connections, pool.max_connections
+        ));
+    }
+    if pool.acquire_timeout_secs == 0 {
+        errors.push(format!("{key}.acquire_timeout_secs must be at least 1"));
+    }
+}
+
+impl Config {
+    /// Loads the configuration file named by `args` (if any), applies the
+    /// overrides in `args` on top and validates the result.
+    pub fn load(args: &ConfigArgs) -> Result<Self, Error> {
+        let mut config = match &args.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        args.apply(&mut config);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn from_env() -> Result<Self, Error> {
+        Self::load(&ConfigArgs::from_env()?)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            Error::Config(vec![format!(
+                "failed to read config file '{}': {e}",
+                path.display()
+            )])
+        })?;
+        toml::from_str(&contents).map_err(|e| {
+            Error::Config(vec![format!(
+                "failed to parse config file '{}': {e}",
+                path.display()
+            )])
+        })
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let mut errors = Vec::new();
+
+        if self.server.max_report_size_mb == 0 {
+            errors.push("server.max_report_size_mb must be at least 1".to_string());
+        }
+
+        if self.auth.enabled && self.storage.backend != StorageBackend::Postgres {
+            errors.push("auth.enabled needs the postgres storage backend".to_string());
+        }
+
+        let database = &self.database;
+        if self.storage.backend == StorageBackend::Postgres {
+            for (value, key, var) in [
+                (&database.host, "database.host", "DB_HOST"),
+                (&database.username, "database.username", "DB_USERNAME"),
+                (&database.password, "database.password", "DB_PASSWORD"),
+                (&database.name, "database.name", "DB_NAME"),
+            ] {
+                if value.as_deref().is_none_or(str::is_empty) {
+                    errors.push(format!("{key} is required (or set {var})"));
+                }
+            }
+        }
+
+        validate_pool("database.pool", &database.pool, &mut errors);
+        if let Some(replica) = &database.replica {
+            if self.storage.backend != StorageBackend::Postgres {
+                errors.push("database.replica needs the postgres storage backend".to_string());
+            }
+            if replica.host.as_deref().is_none_or(str::is_empty) {
+                errors
+                    .push("database.replica.host is required (or set DB_REPLICA_HOST)".to_string());
+            }
+            validate_pool("database.replica.pool", &replica.pool, &mut errors);
+            if replica.health_check_interval_secs == 0 {
+                errors.push(
+                    "database.replica.health_check_interval_secs must be at least 1".to_string(),
+                );
+            }
+        }
+
+        let retention = &self.retention;
+        if retention.max_age_days.is_some() && self.storage.backend != StorageBackend::Postgres {
+            errors.push("retention.max_age_days needs the postgres storage backend".to_string());
+        }
+        if retention.max_age_days == Some(0) {
+            errors.push("retention.max_age_days must be at least 1".to_string());
+        }
+        if retention.interval_secs == 0 {
+            errors.push("retention.interval_secs must be at least 1".to_string());
+        }
+
+        let logging = &self.logging;
+        let invalid_targets: Vec<_> = logging
+            .targets
+            .iter()
+            .filter(|(_, level)| LevelFilter::from_str(level).is_err())
+            .map(|(target, level)| format!("logging.targets.{target}: unknown level '{level}'"))
+            .collect();
+        if invalid_targets.is_empty() {
+            if let Err(Error::Config(filter_errors)) = logging.env_filter() {
+                errors.extend(filter_errors);
+            }
+        }
+        errors.extend(invalid_targets);
+        if let Some(file) = &logging.file {
+            if file.path.as_os_str().is_empty() {
+                errors.push("logging.file.path must not be empty".to_string());
+            }
+            if file.max_size_mb == Some(0) {
+                errors.push("logging.file.max_size_mb must be at least 1".to_string());
+            }
+            if file.max_files == 0 {
+                errors.push("logging.file.max_files must be at least 1".to_string());
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The errors found in `config`, which must be invalid.
+    fn validation_errors(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Err(Error::Config(errors)) => errors,
+            result => panic!("expected a configuration error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn layers_arguments_over_file() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [server]
+            bind_address = "127.0.0.1:8080"
+
+            [database]
+            host = "db.internal"
+            username = "admin"
+            password = "password"
+            name = "poc"
+            ssl_mode = "require"
+
+            [database.pool]
+            max_connections = 5
+            statement_timeout_ms = 2500
+
+            [logging]
+            format = "compact"
+            "#,
+        )
+        .unwrap();
+        let args = ConfigArgs {
+            db_host: Some("localhost".to_string()),
+            db_pool_max_connections: Some(8),
+            ..ConfigArgs::default()
+        };
+        args.apply(&mut config);
+        config.validate().unwrap();
+
+        assert_eq!(config.server.bind_address.port(), 8080);
+        assert_eq!(config.database.host.as_deref(), Some("localhost"));
+        assert!(matches!(config.database.ssl_mode, Some(PgSslMode::Require)));
+        assert_eq!(config.database.pool.max_connections, 8);
+        assert_eq!(config.database.pool.acquire_timeout_secs, 30);
+        assert_eq!(
+            config.database.pool.statement_timeout(),
+            Some(Duration::from_millis(2500))
+        );
+        assert_eq!(config.logging.format, LogFormat::Compact);
+    }
+
+    #[test]
+    fn reports_every_invalid_setting() {
+        let mut config = Config::default();
+        config.database.host = Some("localhost".to_string());
+        config.database.pool.min_connections = 4;
+        config.database.pool.max_connections = 2;
+
+        let errors = validation_errors(&config);
+        assert_eq!(errors.len(), 4, "{errors:?}");
+        assert!(errors[0].contains("DB_USERNAME"));
+        assert!(errors[3].contains("min_connections"));
+
+        config.storage.backend = StorageBackend::Sqlite;
+        config.database.pool.min_connections = 0;
+        config.validate().unwrap();
+
+        assert!(toml::from_str::<Config>("[database]\nssl_mode = \"sometimes\"").is_err());
+        assert!(toml::from_str::<Config>("[server]\nport = 3000").is_err());
+    }
+
+    #[test]
+    fn configures_logging() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [storage]
+            backend = "memory"
+
+            [logging]
+            format = "pretty"
+
+            [logging.targets]
+            sqlx = "loud"
+
+            [logging.file]
+            max_files = 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            validation_errors(&config),
+            [
+                "logging.targets.sqlx: unknown level 'loud'",
+                "logging.file.max_files must be at least 1",
+            ]
+        );
+
+        let args = ConfigArgs {
+            logger: Some(LogFormat::Compact),
+            log_file: Some(PathBuf::from("logs/poc.log")),
+            log_file_max_files: Some(3),
+            ..ConfigArgs::default()
+        };
+        args.apply(&mut config);
+        config
+            .logging
+            .targets
+            .insert("sqlx".to_string(), "warn".to_string());
+        config.validate().unwrap();
+
+        assert_eq!(config.logging.format, LogFormat::Compact);
+        let file = config.logging.file.as_ref().unwrap();
+        assert_eq!(file.path, Path::new("logs/poc.log"));
+        assert_eq!(file.max_files, 3);
+        assert!(file.rotate_daily);
+
+        assert_eq!(parse_legacy_logger("Default"), Ok(LogFormat::Compact));
+        assert!(parse_legacy_logger("Verbose").is_err());
+    }
+
+    #[test]
+    fn configures_replica() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [database]
+            host = "db.internal"
+            username = "admin"
+            password = "password"
+            name = "poc"
+            ssl_mode = "require"
+
+            [database.replica]
+            username = "reader"
+            health_check_interval_secs = 0
+
+            [database.replica.pool]
+            max_connections = 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            validation_errors(&config),
+            [
+                "database.replica.host is required (or set DB_REPLICA_HOST)",
+                "database.replica.pool.max_connections must be at least 1",
+                "database.replica.health_check_interval_secs must be at least 1",
+            ]
+        );
+
+        let args = ConfigArgs {
+            db_replica_host: Some("replica.internal".to_string()),
+            db_replica_health_check_interval_secs: Some(2),
+            db_replica_max_lag_secs: Some(30),
+            ..ConfigArgs::default()
+        };
+        args.apply(&mut config);
+        config
+            .database
+            .replica
+            .as_mut()
+            .unwrap()
+            .pool
+            .max_connections = 10;
+        config.validate().unwrap();
+
+        let replica = config.database.replica().unwrap();
+        assert_eq!(replica.host.as_deref(), Some("replica.internal"));
+        assert_eq!(replica.username.as_deref(), Some("reader"));
+        assert_eq!(replica.password.as_deref(), Some("password"));
+        assert!(matches!(replica.ssl_mode, Some(PgSslMode::Require)));
+        assert_eq!(replica.pool.max_connections, 10);
+        assert!(!replica.migrations.auto_migrate);
+        let replica = config.database.replica.as_ref().unwrap();
+        assert_eq!(replica.health_check_interval(), Duration::from_secs(2));
+        assert_eq!(replica.max_lag(), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn configures_retention() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [storage]
+            backend = "sqlite"
+
+            [retention]
+            max_age_days = 0
+            action = "archive"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            validation_errors(&config),
+            [
+                "retention.max_age_days needs the postgres storage backend",
+                "retention.max_age_days must be at least 1",
+            ]
+        );
+
+        let args = ConfigArgs {
+            storage: Some(StorageBackend::Postgres),
+            db_host: Some("localhost".to_string()),
+            db_username: Some("admin".to_string()),
+            db_password: Some("password".to_string()),
+            db_name: Some("poc".to_string()),
+            retention_max_age_days: Some(90),
+            retention_interval_secs: Some(600),
+            ..ConfigArgs::default()
+        };
+        args.apply(&mut config);
+        config.validate().unwrap();
+
+        assert_eq!(config.retention.action, RetentionAction::Archive);
+        assert_eq!(config.retention.max_age(), Some(chrono::Duration::days(90)));
+        assert_eq!(config.retention.interval(), Duration::from_secs(600));
+        assert!(toml::from_str::<Config>("[retention]\naction = \"delete\"").is_err());
+    }
+}
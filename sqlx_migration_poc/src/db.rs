@@ -1,93 +1,450 @@
+use crate::config::{instance_id, Config, DatabaseConfig, PoolConfig};
 use crate::error::Error;
-use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions, PgSslMode};
-use std::env;
-use std::str::FromStr;
+use crate::metrics;
+use crate::schema::migration::MigrationStatus;
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::pool::PoolConnection;
+use sqlx::postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions, Postgres};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use sqlx::Connection;
+use std::future::Future;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::OnceCell;
-use tracing::{debug, error, info};
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info, warn};
 
-static POOL: OnceCell<PgPool> = OnceCell::const_new();
+static POOLS: OnceCell<PgPools> = OnceCell::const_new();
 
-fn get_env_var(var_name: &str) -> Result<String, Error> {
-    let env_var = env::var(var_name);
-    if env_var.is_err() {
-        return Err(Error::MissingEnvVar(var_name.to_string()));
+/// The migrations embedded in the binary at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("db/migrations");
+
+/// The migrations of the SQLite backend, which only stores test runs.
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("db/sqlite_migrations");
+
+/// Whether the server is a replica, the status of its WAL receiver, and how
+/// far it is behind the primary in seconds. The lag is zero once everything
+/// received has been replayed, which says nothing about what has not been
+/// received unless the receiver is streaming.
+const REPLICA_STATUS_QUERY: &str = "select pg_is_in_recovery(), \
+     (select status from pg_stat_wal_receiver), \
+     case \
+     when pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() then 0 \
+     else extract(epoch from now() - pg_last_xact_replay_timestamp()) \
+     end::float8";
+
+/// The primary pool, which takes every write, and optionally the pool of a
+/// read replica for read-only queries.
+#[derive(Clone, Debug)]
+pub struct PgPools {
+    primary: PgPool,
+    replica: Option<Arc<Replica>>,
+}
+
+#[derive(Debug)]
+struct Replica {
+    pool: PgPool,
+    healthy: AtomicBool,
+    check_timeout: Duration,
+    max_lag: Option<Duration>,
+}
+
+impl PgPools {
+    /// Pools without a replica, so that reads go to the primary as well.
+    pub fn new(primary: PgPool) -> Self {
+        Self {
+            primary,
+            replica: None,
+        }
+    }
+
+    /// Sends reads to `replica` for as long as the health checks run every
+    /// `interval` pass. The first check is done before this returns.
+    pub async fn with_replica(
+        primary: PgPool,
+        replica: PgPool,
+        interval: Duration,
+        max_lag: Option<Duration>,
+    ) -> Self {
+        let replica = Arc::new(Replica {
+            pool: replica,
+            healthy: AtomicBool::new(true),
+            check_timeout: interval,
+            max_lag,
+        });
+        info!("Sending read-only queries to the DB replica while it is healthy");
+        replica.record(replica.check().await);
+        tokio::spawn(monitor(replica.clone(), interval));
+
+        Self {
+            primary,
+            replica: Some(replica),
+        }
+    }
+
+    pub fn primary(&self) -> &PgPool {
+        &self.primary
+    }
+
+    /// The replica pool, if one is configured, whether or not it is healthy.
+    pub fn replica(&self) -> Option<&PgPool> {
+        self.replica.as_ref().map(|replica| &replica.pool)
+    }
+
+    /// The pool for read-only queries: the replica while it is healthy,
+    /// otherwise the primary. Prefer [`Self::read`] for anything that can be
+    /// run again, which does so on the primary when the replica fails.
+    pub fn reader(&self) -> &PgPool {
+        match &self.replica {
+            Some(replica) if replica.healthy.load(Ordering::Relaxed) => &replica.pool,
+            _ => &self.primary,
+        }
+    }
+
+    /// A connection to the primary, for writes.
+    pub(crate) async fn acquire(&self) -> Result<PoolConnection<Postgres>, Error> {
+        Ok(acquire("primary", &self.primary).await?)
+    }
+
+    /// Runs the read-only `query` on a connection from [`Self::reader`]. If
+    /// that is the replica and the connection to it fails, the replica is
+    /// taken out of use until its next successful health check and the query
+    /// is run again on the primary.
+    pub async fn read<T, F, Fut>(&self, query: F) -> Result<T, Error>
+    where
+        F: Fn(PoolConnection<Postgres>) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let Some(replica) = self
+            .replica
+            .as_ref()
+            .filter(|replica| replica.healthy.load(Ordering::Relaxed))
+        else {
+            return query(self.acquire().await?).await;
+        };
+
+        let result = match acquire("replica", &replica.pool).await {
+            Ok(conn) => query(conn).await,
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Err(Error::Sqlx(e)) if lost_connection(&e) => {
+                replica.record(Some(e.to_string()));
+                query(self.acquire().await?).await
+            }
+            result => result,
+        }
+    }
+
+    pub async fn close(&self) {
+        if let Some(replica) = &self.replica {
+            replica.pool.close().await;
+        }
+        self.primary.close().await;
     }
-    Ok(env_var.unwrap())
 }
 
-fn parse_db_port(s: String) -> u16 {
-    s.parse()
-        .unwrap_or_else(|_| panic!("Invalid port number set via environment variable '{}'", s,))
+impl Replica {
+    /// What makes the replica unfit for reads, if anything.
+    async fn check(&self) -> Option<String> {
+        let status = sqlx::query_as::<_, (bool, Option<String>, Option<f64>)>(REPLICA_STATUS_QUERY)
+            .fetch_one(&self.pool);
+        let (in_recovery, receiver, lag) =
+            match tokio::time::timeout(self.check_timeout, status).await {
+                Ok(Ok(status)) => status,
+                Ok(Err(e)) => return Some(e.to_string()),
+                Err(_) => return Some(format!("no response within {:?}", self.check_timeout)),
+            };
+
+        // A server that is not in recovery is never behind.
+        if !in_recovery {
+            return None;
+        }
+        if receiver.as_deref() != Some("streaming") {
+            let receiver = receiver.as_deref().unwrap_or("not running");
+            return Some(format!(
+                "not streaming from the primary, WAL receiver {receiver}"
+            ));
+        }
+        let max_lag = self.max_lag?;
+        let lag = Duration::from_secs_f64(lag.unwrap_or_default().max(0.0));
+        (lag > max_lag).then(|| format!("lagging {lag:?} behind, more than {max_lag:?}"))
+    }
+
+    fn record(&self, problem: Option<String>) {
+        let healthy = problem.is_none();
+        if self.healthy.swap(healthy, Ordering::Relaxed) == healthy {
+            return;
+        }
+        match problem {
+            None => info!("DB replica has recovered, sending reads to it again"),
+            Some(problem) => warn!(
+                problem,
+                "DB replica is unhealthy, sending reads to the primary"
+            ),
+        }
+    }
+}
+
+/// Takes a connection from `pool`, recording how long that took under its
+/// `name` in the pool metrics.
+async fn acquire(name: &str, pool: &PgPool) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+    let started = Instant::now();
+    let conn = pool.acquire().await;
+    metrics::observe_acquire_wait(name, started.elapsed());
+    conn
 }
 
-fn parse_ssl_mode(s: String) -> PgSslMode {
-    match PgSslMode::from_str(&s) {
-        Ok(mode) => mode,
-        Err(_) => {
-            error!(mode = %s, "SSL mode from env is not a valid mode");
-            panic!("Invalid SSL mode set via environment variable '{}'", s)
+/// Whether `e` means the connection to the server failed or was cut, rather
+/// than that the query itself failed.
+fn lost_connection(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => true,
+        // Connection exceptions, and the server shutting down or starting up.
+        sqlx::Error::Database(e) => e.code().is_some_and(|code| {
+            code.starts_with("08") || matches!(&*code, "57P01" | "57P02" | "57P03")
+        }),
+        _ => false,
+    }
+}
+
+/// Checks the replica every `interval` until its pool is closed.
+async fn monitor(replica: Arc<Replica>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        if replica.pool.is_closed() {
+            break;
         }
+        replica.record(replica.check().await);
     }
 }
 
-async fn init_pool(
-    host: &str,
-    port: Option<u16>,
-    username: &str,
-    password: &str,
-    db_name: &str,
-    ssl_mode: Option<PgSslMode>,
-) -> Result<PgPool, Error> {
-    info!("Initializing DB connection pool");
-    let mut opts = PgConnectOptions::new()
-        .host(host)
-        .username(username)
-        .password(password)
-        .database(db_name);
+/// Key of the advisory lock that lets only one instance migrate at a time.
+const MIGRATION_LOCK_ID: i64 = 0x7371_6c78_6d69_6772;
+const MIGRATION_LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn connect_options(config: &DatabaseConfig) -> PgConnectOptions {
+    let mut opts = PgConnectOptions::new().application_name(instance_id());
+    if let Some(host) = &config.host {
+        opts = opts.host(host);
+    }
+    if let Some(username) = &config.username {
+        opts = opts.username(username);
+    }
+    if let Some(password) = &config.password {
+        opts = opts.password(password);
+    }
+    if let Some(name) = &config.name {
+        opts = opts.database(name);
+    }
 
-    match port {
-        None => debug!("No DB port found in env, using default"),
+    match config.port {
+        None => debug!("No DB port configured, using default"),
         Some(port) => {
             debug!(port, "Setting DB port");
             opts = opts.port(port);
         }
     }
 
-    match ssl_mode {
-        None => debug!("No SSL mode found in env, using default"),
+    match config.ssl_mode {
+        None => debug!("No SSL mode configured, using default"),
         Some(mode) => {
             debug!(?mode, "Setting SSL mode");
             opts = opts.ssl_mode(mode);
         }
     }
 
-    let pool = PgPoolOptions::new()
-        .max_connections(20)
-        .connect_with(opts)
+    if let Some(timeout) = config.pool.statement_timeout() {
+        debug!(?timeout, "Setting statement timeout");
+        opts = opts.options([("statement_timeout", timeout.as_millis().to_string())]);
+    }
+
+    opts
+}
+
+fn pool_options(pool_config: &PoolConfig) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .min_connections(pool_config.min_connections)
+        .max_connections(pool_config.max_connections)
+        .acquire_timeout(pool_config.acquire_timeout())
+        .idle_timeout(pool_config.idle_timeout())
+}
+
+pub async fn init_pool(config: &DatabaseConfig) -> Result<PgPool, Error> {
+    let pool_config = &config.pool;
+    info!(
+        min_connections = pool_config.min_connections,
+        max_connections = pool_config.max_connections,
+        "Initializing DB connection pool"
+    );
+
+    let pool = pool_options(pool_config)
+        .connect_with(connect_options(config))
         .await?;
 
     Ok(pool)
 }
 
+/// Connects using only the settings found in the environment.
 pub async fn init_pool_from_env() -> Result<PgPool, Error> {
-    info!("Fetching DB variables from environment");
-    let host = get_env_var("DB_HOST")?;
-    let port = get_env_var("DB_PORT").ok().map(parse_db_port);
-    let username = get_env_var("DB_USERNAME")?;
-    let password = get_env_var("DB_PASSWORD")?;
-    let database_name = get_env_var("DB_NAME")?;
-    let ssl_mode = get_env_var("DB_SSL_MODE").ok().map(parse_ssl_mode);
-    init_pool(&host, port, &username, &password, &database_name, ssl_mode).await
+    init_pool(&Config::from_env()?.database).await
 }
 
-pub async fn init_pool_from_env_and_migrate() -> Result<PgPool, Error> {
-    let pool = init_pool_from_env().await?;
-    info!("Running DB migrations");
-    sqlx::migrate!("db/migrations").run(&pool).await?;
+/// Returns the `application_name` of the session holding the migration lock.
+async fn migration_lock_holder(conn: &mut PgConnection) -> Result<Option<String>, Error> {
+    Ok(sqlx::query_scalar(
+        "select a.application_name from pg_locks l \
+         join pg_stat_activity a on a.pid = l.pid \
+         where l.locktype = 'advisory' and l.granted \
+         and l.classid::int8 = $1 and l.objid::int8 = $2 and l.objsubid = 1",
+    )
+    .bind(MIGRATION_LOCK_ID >> 32)
+    .bind(MIGRATION_LOCK_ID & 0xffff_ffff)
+    .fetch_optional(conn)
+    .await?)
+}
+
+async fn migrate_locked(conn: &mut PgConnection, lock_timeout: Duration) -> Result<usize, Error> {
+    let started = Instant::now();
+    let mut waiting = false;
+    loop {
+        let locked: bool = sqlx::query_scalar("select pg_try_advisory_lock($1)")
+            .bind(MIGRATION_LOCK_ID)
+            .fetch_one(&mut *conn)
+            .await?;
+        if locked {
+            break;
+        }
+        if started.elapsed() >= lock_timeout {
+            return Err(Error::MigrationLockTimeout(lock_timeout));
+        }
+        if !waiting {
+            let holder = migration_lock_holder(conn).await?;
+            info!(
+                instance = instance_id(),
+                holder, "Waiting for another instance to finish migrating"
+            );
+            waiting = true;
+        }
+        tokio::time::sleep(MIGRATION_LOCK_RETRY_INTERVAL).await;
+    }
+
+    conn.ensure_migrations_table().await?;
+    let before = conn.list_applied_migrations().await?.len();
+    MIGRATOR.run_direct(&mut *conn).await?;
+    let applied = conn.list_applied_migrations().await?.len() - before;
+
+    if applied > 0 {
+        info!(instance = instance_id(), applied, "Applied DB migrations");
+    } else {
+        info!(instance = instance_id(), "DB schema is already up to date");
+    }
+
+    Ok(applied)
+}
+
+/// Applies pending migrations while holding a Postgres advisory lock, so that
+/// replicas starting at the same time do not race each other. Gives up if the
+/// lock cannot be taken within `lock_timeout`. Returns the number of
+/// migrations applied by this instance.
+pub async fn migrate(pool: &PgPool, lock_timeout: Duration) -> Result<usize, Error> {
+    // A dedicated connection, so that closing it always releases the lock.
+    let mut conn = PgConnection::connect_with(&pool.connect_options()).await?;
+    let result = migrate_locked(&mut conn, lock_timeout).await;
+    conn.close().await?;
+
+    result
+}
+
+pub async fn init_pool_and_migrate(config: &DatabaseConfig) -> Result<PgPool, Error> {
+    let pool = init_pool(config).await?;
+
+    if config.migrations.auto_migrate {
+        info!("Running DB migrations");
+        migrate(&pool, config.migrations.lock_timeout()).await?;
+    } else {
+        let pending = MigrationStatus::get_all(&pool)
+            .await?
+            .iter()
+            .filter(|m| !m.applied)
+            .count();
+        if pending > 0 {
+            warn!(
+                pending,
+                "Auto-migration is disabled and the DB has pending migrations"
+            );
+        } else {
+            info!("Auto-migration is disabled, skipping DB migrations");
+        }
+    }
 
     Ok(pool)
 }
 
-pub async fn get_pool() -> Result<&'static PgPool, Error> {
-    POOL.get_or_try_init(init_pool_from_env_and_migrate).await
+/// Opens (creating it if needed) the SQLite database at `path`, sized and
+/// migrated according to the same settings as the Postgres pool.
+pub async fn init_sqlite_pool(path: &Path, config: &DatabaseConfig) -> Result<SqlitePool, Error> {
+    info!(path = %path.display(), "Opening SQLite database");
+    let opts = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .foreign_keys(true)
+        .journal_mode(SqliteJournalMode::Wal);
+
+    let pool_config = &config.pool;
+    let pool = SqlitePoolOptions::new()
+        .min_connections(pool_config.min_connections)
+        .max_connections(pool_config.max_connections)
+        .acquire_timeout(pool_config.acquire_timeout())
+        .idle_timeout(pool_config.idle_timeout())
+        .connect_with(opts)
+        .await?;
+
+    if config.migrations.auto_migrate {
+        info!("Running SQLite migrations");
+        SQLITE_MIGRATOR.run(&pool).await?;
+    }
+
+    Ok(pool)
+}
+
+/// Connects to the primary, migrating it as configured, and to the replica if
+/// there is one. The replica is connected to lazily, so that the service
+/// starts, reading from the primary, while the replica is down.
+pub async fn init_pools(config: &DatabaseConfig) -> Result<PgPools, Error> {
+    let primary = init_pool_and_migrate(config).await?;
+    let (Some(replica), Some(replica_config)) = (config.replica(), &config.replica) else {
+        return Ok(PgPools::new(primary));
+    };
+
+    info!(
+        host = ?replica.host,
+        max_connections = replica.pool.max_connections,
+        "Initializing DB replica connection pool"
+    );
+    let replica_pool = pool_options(&replica.pool).connect_lazy_with(connect_options(&replica));
+    Ok(PgPools::with_replica(
+        primary,
+        replica_pool,
+        replica_config.health_check_interval(),
+        replica_config.max_lag(),
+    )
+    .await)
+}
+
+pub async fn get_pools(config: &DatabaseConfig) -> Result<&'static PgPools, Error> {
+    POOLS.get_or_try_init(|| init_pools(config)).await
+}
+
+pub async fn get_pool(config: &DatabaseConfig) -> Result<&'static PgPool, Error> {
+    Ok(get_pools(config).await?.primary())
 }
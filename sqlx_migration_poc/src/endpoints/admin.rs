@@ -0,0 +1,35 @@
+use crate::endpoints::extract::{Json, Path};
+use crate::error::Error;
+use crate::schema::api_token::{ApiToken, IssuedApiToken, NewApiToken};
+use axum::http::StatusCode;
+use axum::Extension;
+use sqlx::PgPool;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// Issues a token. The response is the only place the token ever appears.
+pub async fn issue_token(
+    pool: Extension<&PgPool>,
+    Json(new_api_token): Json<NewApiToken>,
+) -> Result<(StatusCode, Json<IssuedApiToken>), Error> {
+    info!("Received an HTTP 'POST' request at the '/admin/tokens' endpoint");
+    debug!(name = %new_api_token.name, scopes = ?new_api_token.scopes, "with body");
+    let issued = ApiToken::issue(new_api_token, &pool).await?;
+
+    Ok((StatusCode::CREATED, Json(issued)))
+}
+
+pub async fn list_tokens(pool: Extension<&PgPool>) -> Result<Json<Vec<ApiToken>>, Error> {
+    info!("Received an HTTP 'GET' request at the '/admin/tokens' endpoint");
+    Ok(Json(ApiToken::get_all(&pool).await?))
+}
+
+pub async fn revoke_token(
+    pool: Extension<&PgPool>,
+    Path(api_token_id): Path<Uuid>,
+) -> Result<StatusCode, Error> {
+    info!("Received an HTTP 'DELETE' request at the '/admin/tokens/{api_token_id}' endpoint");
+    ApiToken::revoke(api_token_id, &pool).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,112 @@
+use crate::db::PgPools;
+use crate::endpoints::extract::{Json, Query};
+use crate::endpoints::{default_since, default_until};
+use crate::error::{Error, FieldError};
+use crate::schema::analytics::{FailureRate, FlakyTest, SlowTest};
+use async_graphql::InputObject;
+use axum::Extension;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Debug, Serialize, Deserialize, InputObject)]
+#[graphql(name = "AnalyticsFilter")]
+pub struct AnalyticsQueryParams {
+    pub(crate) suite_name: Option<String>,
+    pub(crate) min_runs: Option<u32>,
+    pub(crate) limit: Option<u32>,
+    #[serde(default = "default_since")]
+    #[graphql(default_with = "default_since()")]
+    pub(crate) since: DateTime<Utc>,
+    #[serde(default = "default_until")]
+    #[graphql(default_with = "default_until()")]
+    pub(crate) until: DateTime<Utc>,
+}
+
+impl Default for AnalyticsQueryParams {
+    fn default() -> Self {
+        Self {
+            suite_name: None,
+            min_runs: None,
+            limit: None,
+            since: default_since(),
+            until: default_until(),
+        }
+    }
+}
+
+impl AnalyticsQueryParams {
+    /// Tests with fewer (non-skipped) results than this in the window are
+    /// left out of the aggregates.
+    pub(crate) fn min_runs(&self) -> u32 {
+        self.min_runs.unwrap_or(1)
+    }
+
+    pub(crate) fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        if !(1..=MAX_LIMIT).contains(&self.limit()) {
+            errors.push(FieldError::new(
+                "limit",
+                format!("must be between 1 and {MAX_LIMIT}"),
+            ));
+        }
+        if self.since > self.until {
+            errors.push(FieldError::new("since", "must not be after 'until'"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(errors))
+        }
+    }
+}
+
+pub async fn failure_rates(
+    pools: Extension<&PgPools>,
+    Query(query_params): Query<AnalyticsQueryParams>,
+) -> Result<Json<Vec<FailureRate>>, Error> {
+    info!("Received an HTTP 'GET' request at the '/analytics/failure-rates' endpoint");
+    debug!("with query params: {:?}", query_params);
+    let query_params = &query_params;
+    Ok(Json(
+        pools
+            .read(|mut conn| async move { FailureRate::get(query_params, &mut conn).await })
+            .await?,
+    ))
+}
+
+pub async fn flaky_tests(
+    pools: Extension<&PgPools>,
+    Query(query_params): Query<AnalyticsQueryParams>,
+) -> Result<Json<Vec<FlakyTest>>, Error> {
+    info!("Received an HTTP 'GET' request at the '/analytics/flaky-tests' endpoint");
+    debug!("with query params: {:?}", query_params);
+    let query_params = &query_params;
+    Ok(Json(
+        pools
+            .read(|mut conn| async move { FlakyTest::get(query_params, &mut conn).await })
+            .await?,
+    ))
+}
+
+pub async fn slowest_tests(
+    pools: Extension<&PgPools>,
+    Query(query_params): Query<AnalyticsQueryParams>,
+) -> Result<Json<Vec<SlowTest>>, Error> {
+    info!("Received an HTTP 'GET' request at the '/analytics/slowest-tests' endpoint");
+    debug!("with query params: {:?}", query_params);
+    let query_params = &query_params;
+    Ok(Json(
+        pools
+            .read(|mut conn| async move { SlowTest::get(query_params, &mut conn).await })
+            .await?,
+    ))
+}
@@ -0,0 +1,174 @@
+use crate::endpoints::extract::Query;
+use crate::error::{Error, FieldError};
+use crate::events::TestRunEvents;
+use crate::schema::test_run_event::TestRunEvent;
+use crate::Draining;
+use axum::extract::ws::rejection::WebSocketUpgradeRejection;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::http::{HeaderMap, HeaderName};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use futures_util::stream::BoxStream;
+use futures_util::{StreamExt, TryStreamExt};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+use utoipa::IntoParams;
+
+static LAST_EVENT_ID: HeaderName = HeaderName::from_static("last-event-id");
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventQueryParams {
+    /// Resume after this event. For clients that cannot send `Last-Event-ID`,
+    /// which takes precedence.
+    pub(crate) last_event_id: Option<i64>,
+}
+
+impl EventQueryParams {
+    /// The event to resume after, from the `Last-Event-ID` header that
+    /// `EventSource` sends when reconnecting, or else from the query.
+    fn last_event_id(&self, headers: &HeaderMap) -> Result<Option<i64>, Error> {
+        let Some(value) = headers.get(&LAST_EVENT_ID) else {
+            return Ok(self.last_event_id);
+        };
+        match value.to_str().map(str::trim) {
+            Ok("") => Ok(None),
+            value => value
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .map(Some)
+                .ok_or_else(|| {
+                    Error::Validation(vec![FieldError::new(
+                        "Last-Event-ID",
+                        "must be the id of an event",
+                    )])
+                }),
+        }
+    }
+}
+
+/// Cancelled when the server starts shutting down, or never outside of
+/// [`crate::serve`].
+fn shutdown(draining: Option<Extension<Draining>>) -> CancellationToken {
+    draining
+        .map(|Extension(Draining(token))| token)
+        .unwrap_or_default()
+}
+
+fn sse_event(event: &TestRunEvent) -> Event {
+    Event::default()
+        .id(event.event_id.to_string())
+        .event(event.kind.as_str())
+        .json_data(event)
+        .expect("test run events always serialize")
+}
+
+/// Streams test run events as Server-Sent Events named after their kind. The
+/// stream ends when the client falls behind or the server shuts down, after
+/// which the client resumes by sending the id of the last event it received.
+#[utoipa::path(
+    get,
+    path = "/test-runs/events",
+    operation_id = "streamTestRunEvents",
+    tag = "test-runs",
+    params(
+        EventQueryParams,
+        ("Last-Event-ID" = Option<i64>, Header, description = "Resume after this event"),
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events, one per created or updated test run",
+            body = TestRunEvent, content_type = "text/event-stream"),
+        (status = 400, description = "Malformed request", body = Problem, content_type = "application/problem+json"),
+        (status = 410, description = "The event to resume after no longer exists", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid event id", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn stream(
+    events: Extension<TestRunEvents>,
+    draining: Option<Extension<Draining>>,
+    headers: HeaderMap,
+    Query(query_params): Query<EventQueryParams>,
+) -> Result<impl IntoResponse, Error> {
+    info!("Received an HTTP 'GET' request at the '/test-runs/events' endpoint");
+    let last_event_id = query_params.last_event_id(&headers)?;
+    debug!(?last_event_id, "with last event id");
+
+    let events = events
+        .subscribe(last_event_id)
+        .await?
+        .take_until(shutdown(draining).cancelled_owned())
+        .map_ok(|event| sse_event(&event))
+        .inspect_err(|e| error!("Event stream failed: {e}"));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Sends test run events as JSON text messages over a WebSocket. The socket is
+/// closed with code 1013 (try again later) when the client falls behind, and
+/// 1001 (going away) when the server shuts down.
+#[utoipa::path(
+    get,
+    path = "/test-runs/events/ws",
+    operation_id = "subscribeToTestRunEvents",
+    tag = "test-runs",
+    params(EventQueryParams),
+    responses(
+        (status = 101, description = "Switched to a WebSocket carrying one TestRunEvent per text message"),
+        (status = 400, description = "Malformed request", body = Problem, content_type = "application/problem+json"),
+        (status = 410, description = "The event to resume after no longer exists", body = Problem, content_type = "application/problem+json"),
+        (status = 426, description = "Not a WebSocket handshake", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn websocket(
+    events: Extension<TestRunEvents>,
+    draining: Option<Extension<Draining>>,
+    Query(query_params): Query<EventQueryParams>,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Result<Response, Error> {
+    info!("Received an HTTP 'GET' request at the '/test-runs/events/ws' endpoint");
+    debug!("with query params: {:?}", query_params);
+    let upgrade = upgrade?;
+
+    let events = events.subscribe(query_params.last_event_id).await?;
+    let shutdown = shutdown(draining);
+    Ok(upgrade.on_upgrade(move |socket| send_events(socket, events, shutdown)))
+}
+
+async fn send_events(
+    mut socket: WebSocket,
+    mut events: BoxStream<'static, Result<Arc<TestRunEvent>, Error>>,
+    shutdown: CancellationToken,
+) {
+    let (code, reason) = loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(Ok(event)) => {
+                    let text = serde_json::to_string(&*event)
+                        .expect("test run events always serialize");
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Err(e)) => {
+                    error!("Event stream failed: {e}");
+                    break (close_code::ERROR, "event stream failed");
+                }
+                None => break (close_code::AGAIN, "resume from the last event id"),
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+            _ = shutdown.cancelled() => break (close_code::AWAY, "server is shutting down"),
+        }
+    };
+
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
@@ -0,0 +1,166 @@
+use crate::db::PgPools;
+use crate::endpoints::extract::Query;
+use crate::endpoints::{default_since, default_until};
+use crate::error::{Error, FieldError};
+use crate::schema::test_run::TestRun;
+use axum::body::{Body, Bytes};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Extension;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde::Deserialize;
+use tracing::{debug, error, info};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Ndjson,
+    Csv,
+}
+
+impl ExportFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "test-runs.ndjson",
+            ExportFormat::Csv => "test-runs.csv",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQueryParams {
+    #[serde(default)]
+    pub(crate) format: ExportFormat,
+    #[serde(default = "default_since")]
+    pub(crate) since: DateTime<Utc>,
+    #[serde(default = "default_until")]
+    pub(crate) until: DateTime<Utc>,
+}
+
+impl ExportQueryParams {
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.since > self.until {
+            return Err(Error::Validation(vec![FieldError::new(
+                "since",
+                "must not be after 'until'",
+            )]));
+        }
+        Ok(())
+    }
+}
+
+const CSV_HEADER: &str = "test_run_id,build_number,build_url,build_timestamp\r\n";
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break, as
+/// described in RFC 4180.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_record(test_run: &TestRun) -> String {
+    format!(
+        "{},{},{},{}\r\n",
+        test_run.test_run_id,
+        csv_field(&test_run.build_number),
+        csv_field(test_run.build_url.as_deref().unwrap_or_default()),
+        test_run
+            .build_timestamp
+            .to_rfc3339_opts(SecondsFormat::Micros, true),
+    )
+}
+
+fn ndjson_record(test_run: &TestRun) -> String {
+    let mut line = serde_json::to_string(test_run).expect("test runs always serialize");
+    line.push('\n');
+    line
+}
+
+/// Streams every test run in the `since`/`until` window as NDJSON or CSV. Rows
+/// are encoded as they arrive from the database and sent as a chunked body,
+/// so the size of the window does not affect the memory used.
+#[utoipa::path(
+    get,
+    path = "/test-runs/export",
+    operation_id = "exportTestRuns",
+    tag = "test-runs",
+    params(ExportQueryParams),
+    responses(
+        (status = 200, description = "Every test run in the window, newest first",
+            content(
+                ("application/x-ndjson" = String),
+                ("text/csv" = String),
+            )),
+        (status = 400, description = "Malformed request", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid field values", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn export(
+    pools: Extension<&'static PgPools>,
+    Query(query_params): Query<ExportQueryParams>,
+) -> Result<impl IntoResponse, Error> {
+    info!("Received an HTTP 'GET' request at the '/test-runs/export' endpoint");
+    debug!("with query params: {:?}", query_params);
+    query_params.validate()?;
+
+    let format = query_params.format;
+    let rows = TestRun::stream(query_params.since, query_params.until, pools.reader());
+    let records = match format {
+        ExportFormat::Ndjson => rows.map_ok(|run| ndjson_record(&run)).boxed(),
+        ExportFormat::Csv => stream::once(async { Ok(CSV_HEADER.to_string()) })
+            .chain(rows.map_ok(|run| csv_record(&run)))
+            .boxed(),
+    };
+    // An error after the headers have gone out can only abort the response.
+    let body = records
+        .map_ok(Bytes::from)
+        .inspect_err(|e| error!("Export failed: {e}"));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", format.file_name()),
+            ),
+        ],
+        Body::from_stream(body),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    #[test]
+    fn escapes_csv_fields() {
+        let test_run = TestRun {
+            test_run_id: Uuid::nil(),
+            build_number: "7, \"nightly\"".to_string(),
+            build_url: None,
+            build_timestamp: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+        };
+
+        assert_eq!(
+            csv_record(&test_run),
+            "00000000-0000-0000-0000-000000000000,\"7, \"\"nightly\"\"\",,\
+             2024-03-01T12:00:00.000000Z\r\n"
+        );
+    }
+}
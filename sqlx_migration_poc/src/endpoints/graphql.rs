@@ -0,0 +1,17 @@
+use crate::db::PgPools;
+use crate::endpoints::extract::Json;
+use crate::graphql::{self, ApiSchema};
+use axum::Extension;
+use tracing::{debug, info};
+
+pub async fn graphql(
+    pools: Extension<&'static PgPools>,
+    Extension(schema): Extension<ApiSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    info!("Received an HTTP 'POST' request at the '/graphql' endpoint");
+    debug!(operation = ?request.operation_name, "with GraphQL request");
+    let request = request.data(graphql::results_loader(&pools));
+
+    Json(schema.execute(request).await)
+}
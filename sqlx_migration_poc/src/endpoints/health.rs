@@ -0,0 +1,72 @@
+use crate::endpoints::extract::Json;
+use crate::error::Error;
+use crate::metrics;
+use crate::repository::{Storage, TestRunRepository};
+use crate::schema::migration::MigrationStatus;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Extension;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// How long `/readyz` waits for a connection and the probe query combined.
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    status: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Migrations {
+    up_to_date: bool,
+    pending: usize,
+    migrations: Vec<MigrationStatus>,
+}
+
+/// Liveness probe; succeeds for as long as the process can serve requests.
+pub async fn healthz() -> Json<Health> {
+    debug!("Received an HTTP 'GET' request at the '/healthz' endpoint");
+    Json(Health { status: "ok" })
+}
+
+/// Readiness probe; succeeds when the storage backend can run a query within
+/// [`READY_TIMEOUT`].
+pub async fn readyz(
+    repository: Extension<Arc<dyn TestRunRepository>>,
+) -> Result<Json<Health>, Error> {
+    debug!("Received an HTTP 'GET' request at the '/readyz' endpoint");
+    match tokio::time::timeout(READY_TIMEOUT, repository.ping()).await {
+        Ok(Ok(_)) => Ok(Json(Health { status: "ready" })),
+        Ok(Err(e)) => Err(Error::Unavailable(format!("Database is unreachable: {e}"))),
+        Err(_) => Err(Error::Unavailable(format!(
+            "Database did not respond within {READY_TIMEOUT:?}"
+        ))),
+    }
+}
+
+pub async fn migrations(pool: Extension<&PgPool>) -> Result<Json<Migrations>, Error> {
+    info!("Received an HTTP 'GET' request at the '/migrations' endpoint");
+    let migrations = MigrationStatus::get_all(&pool).await?;
+    let pending = migrations.iter().filter(|m| !m.applied).count();
+    let up_to_date = pending == 0 && migrations.iter().all(|m| m.checksum_matches == Some(true));
+
+    Ok(Json(Migrations {
+        up_to_date,
+        pending,
+        migrations,
+    }))
+}
+
+/// Request and connection pool metrics in the Prometheus text format.
+pub async fn metrics(storage: Extension<Storage>) -> impl IntoResponse {
+    debug!("Received an HTTP 'GET' request at the '/metrics' endpoint");
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(&storage),
+    )
+}
@@ -0,0 +1,126 @@
+use crate::db::PgPools;
+use crate::endpoints::extract::{Json, Query};
+use crate::error::Error;
+use crate::report::{self, ReportFormat};
+use crate::schema::test_run::{NewTestRun, TestRun};
+use axum::body::Body;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::Extension;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::io;
+use tokio::io::AsyncReadExt;
+use tokio_util::io::StreamReader;
+use tracing::{debug, info};
+use utoipa::{IntoParams, ToSchema};
+
+/// The largest report, in bytes, that [`import`] reads.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MaxReportSize(pub(crate) u64);
+
+fn default_suite_name() -> String {
+    "default".to_string()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQueryParams {
+    pub(crate) format: Option<ReportFormat>,
+    pub(crate) build_number: String,
+    pub(crate) build_url: Option<String>,
+    pub(crate) build_timestamp: Option<DateTime<Utc>>,
+    #[serde(default = "default_suite_name")]
+    pub(crate) suite_name: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedTestRun {
+    test_run: TestRun,
+    result_count: u64,
+}
+
+/// Creates a test run from a JUnit XML or TAP report. The report format comes
+/// from the `format` query parameter, or failing that the `Content-Type`.
+#[utoipa::path(
+    post,
+    path = "/test-runs/import",
+    operation_id = "importTestRun",
+    tag = "test-runs",
+    params(ImportQueryParams),
+    request_body(content = String, description = "A JUnit XML or TAP report",
+        content_type = "application/xml"),
+    responses(
+        (status = 201, description = "The new test run and the number of results recorded",
+            body = ImportedTestRun,
+            headers(("location" = String, description = "URL of the new test run"))),
+        (status = 400, description = "Malformed request or report", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The build number is already taken", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "The report is too large", body = Problem, content_type = "application/problem+json"),
+        (status = 415, description = "Unknown report format", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid field values", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn import(
+    pools: Extension<&PgPools>,
+    Extension(MaxReportSize(max_size)): Extension<MaxReportSize>,
+    headers: HeaderMap,
+    Query(query_params): Query<ImportQueryParams>,
+    body: Body,
+) -> Result<impl IntoResponse, Error> {
+    info!("Received an HTTP 'POST' request at the '/test-runs/import' endpoint");
+    debug!("with query params: {:?}", query_params);
+    let format = query_params
+        .format
+        .or_else(|| ReportFormat::from_headers(&headers))
+        .ok_or_else(|| Error::Rejection {
+            status: StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            message: "Specify the report format with `format=junit|tap` or a JUnit XML or TAP \
+                      content type"
+                .to_string(),
+        })?;
+
+    let new_test_run = NewTestRun {
+        build_number: query_params.build_number,
+        build_url: query_params.build_url,
+        build_timestamp: query_params.build_timestamp,
+    };
+    new_test_run.validate()?;
+
+    let too_large = || Error::Rejection {
+        status: StatusCode::PAYLOAD_TOO_LARGE,
+        message: format!("Reports may be at most {max_size} bytes"),
+    };
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > max_size) {
+        return Err(too_large());
+    }
+
+    // Reading one byte past the limit tells a report that is too large apart
+    // from one that is exactly as large as allowed.
+    let stream = body.into_data_stream().map_err(io::Error::other);
+    let mut reader = StreamReader::new(stream).take(max_size + 1);
+    let parsed = report::parse(format, &mut reader, &query_params.suite_name).await;
+    if reader.limit() == 0 {
+        return Err(too_large());
+    }
+    let results = parsed?;
+    debug!(?format, "parsed {} results", results.len());
+
+    let (test_run, result_count) =
+        TestRun::create_with_results(new_test_run, &results, &mut *pools.acquire().await?).await?;
+
+    let location = format!("/test-runs/{}", test_run.test_run_id);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(ImportedTestRun {
+            test_run,
+            result_count,
+        }),
+    ))
+}
@@ -1,9 +1,21 @@
-use axum::http::StatusCode;
-use axum::routing::get;
-use axum::{Extension, Router};
+use crate::config::Config;
+use crate::events::TestRunEvents;
+use crate::repository::Storage;
+use crate::{auth, graphql as api_graphql, metrics, request_id};
+use axum::routing::{delete, get, post, MethodRouter};
+use axum::{middleware, Extension, Router};
 use chrono::{DateTime, Duration, Utc};
-use sqlx::{Error, PgPool};
 
+pub(crate) mod admin;
+pub(crate) mod analytics;
+pub(crate) mod events;
+pub(crate) mod export;
+mod extract;
+pub(crate) mod graphql;
+pub(crate) mod health;
+pub(crate) mod import;
+pub(crate) mod openapi;
+pub(crate) mod test_result;
 pub(crate) mod test_run;
 
 pub fn default_until() -> DateTime<Utc> {
@@ -14,21 +26,88 @@ pub fn default_since() -> DateTime<Utc> {
     default_until() - Duration::days(712)
 }
 
-pub fn sqlx_err_to_status_code(value: Error) -> StatusCode {
-    match value {
-        Error::RowNotFound
-        | Error::TypeNotFound { .. }
-        | Error::ColumnIndexOutOfBounds { .. }
-        | Error::ColumnNotFound(_)
-        | Error::ColumnDecode { .. }
-        | Error::Decode(_)
-        | Error::Database(..) => StatusCode::BAD_REQUEST,
-        _ => StatusCode::INTERNAL_SERVER_ERROR,
-    }
+/// The `/test-runs` routes that every backend serves. Each of them, like
+/// those of [`pg_test_run_routes`], is documented in [`openapi::ApiDoc`].
+pub(crate) fn test_run_routes() -> Vec<(&'static str, MethodRouter)> {
+    vec![
+        ("/test-runs", get(test_run::list).post(test_run::create)),
+        (
+            "/test-runs/:test_run_id",
+            get(test_run::get)
+                .patch(test_run::update)
+                .delete(test_run::delete),
+        ),
+    ]
+}
+
+/// The `/test-runs` routes that need Postgres.
+pub(crate) fn pg_test_run_routes() -> Vec<(&'static str, MethodRouter)> {
+    vec![
+        ("/test-runs/export", get(export::export)),
+        ("/test-runs/import", post(import::import)),
+        ("/test-runs/events", get(events::stream)),
+        ("/test-runs/events/ws", get(events::websocket)),
+        (
+            "/test-runs/:test_run_id/results",
+            get(test_result::list).post(test_result::submit),
+        ),
+    ]
 }
 
-pub fn create_routes(pool: &'static PgPool) -> Router {
-    Router::new()
-        .route("/test-runs", get(test_run::handler))
-        .layer(Extension(pool))
+/// Builds the router for `storage`. Routes that need Postgres are only
+/// mounted when running on Postgres.
+///
+/// # Panics
+///
+/// If authentication is enabled on a backend other than Postgres, which the
+/// configuration rejects.
+pub fn create_routes(storage: &Storage, config: &Config) -> Router {
+    let mut router = test_run_routes()
+        .into_iter()
+        .fold(Router::new(), |router, (path, route)| {
+            router.route(path, route)
+        })
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(health::metrics))
+        .route("/openapi.json", get(openapi::openapi));
+
+    if let Some(pools) = storage.pg_pools() {
+        let pool = pools.primary();
+        router = pg_test_run_routes()
+            .into_iter()
+            .fold(router, |router, (path, route)| router.route(path, route))
+            .route("/analytics/failure-rates", get(analytics::failure_rates))
+            .route("/analytics/flaky-tests", get(analytics::flaky_tests))
+            .route("/analytics/slowest-tests", get(analytics::slowest_tests))
+            .route("/migrations", get(health::migrations))
+            .route(
+                "/admin/tokens",
+                get(admin::list_tokens).post(admin::issue_token),
+            )
+            .route("/admin/tokens/:api_token_id", delete(admin::revoke_token))
+            .route("/graphql", post(graphql::graphql))
+            .layer(Extension(import::MaxReportSize(
+                config.server.max_report_size(),
+            )))
+            .layer(Extension(api_graphql::schema(pools)))
+            .layer(Extension(TestRunEvents::new(pool)))
+            .layer(Extension(pools))
+            .layer(Extension(pool));
+    }
+
+    router = router
+        .layer(Extension(storage.test_runs()))
+        .layer(Extension(storage.clone()));
+
+    if config.auth.enabled {
+        let pool = storage
+            .pg_pool()
+            .expect("authentication needs the Postgres backend");
+        router = router.layer(middleware::from_fn_with_state(pool, auth::authenticate));
+    }
+
+    router
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(request_id::propagate))
 }
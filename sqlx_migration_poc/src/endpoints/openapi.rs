@@ -0,0 +1,133 @@
+use crate::endpoints::extract::Json;
+use crate::endpoints::{events, export, import, test_result, test_run};
+use crate::error::{FieldError, Problem};
+use crate::schema::test_result::{NewTestResult, TestResult, TestStatus};
+use crate::schema::test_run::{NewTestRun, TestRun, TestRunPatch};
+use crate::schema::test_run_event::{TestRunEvent, TestRunEventKind};
+use tracing::debug;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::OpenApi as OpenApiDocument;
+use utoipa::{Modify, OpenApi};
+
+/// Declares the bearer tokens that the API asks for when authentication is
+/// enabled.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+    }
+}
+
+/// The OpenAPI document of the test run endpoints, generated from the
+/// handlers and the types they exchange.
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        test_run::list,
+        test_run::create,
+        test_run::get,
+        test_run::update,
+        test_run::delete,
+        export::export,
+        import::import,
+        events::stream,
+        events::websocket,
+        test_result::list,
+        test_result::submit,
+    ),
+    components(schemas(
+        TestRun,
+        NewTestRun,
+        TestRunPatch,
+        TestResult,
+        NewTestResult,
+        TestStatus,
+        TestRunEvent,
+        TestRunEventKind,
+        export::ExportFormat,
+        import::ImportedTestRun,
+        crate::report::ReportFormat,
+        test_result::SubmittedResults,
+        Problem,
+        FieldError,
+    )),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+    tags((name = "test-runs", description = "CI test runs and their results"))
+)]
+pub struct ApiDoc;
+
+pub async fn openapi() -> Json<OpenApiDocument> {
+    debug!("Received an HTTP 'GET' request at the '/openapi.json' endpoint");
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoints::{pg_test_run_routes, test_run_routes};
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use axum::Router;
+    use std::collections::BTreeSet;
+    use tower::ServiceExt;
+
+    const METHODS: [Method; 5] = [
+        Method::GET,
+        Method::POST,
+        Method::PUT,
+        Method::PATCH,
+        Method::DELETE,
+    ];
+
+    /// The `(path, method)` pairs that the `/test-runs` routes serve, in
+    /// OpenAPI notation. A `MethodRouter` cannot list its methods, so each
+    /// route is asked for every method on its own: a 405 means it has none.
+    /// The handlers find none of their extensions, so nothing else is run.
+    async fn routed_operations() -> BTreeSet<(String, String)> {
+        let mut operations = BTreeSet::new();
+        for (path, route) in test_run_routes().into_iter().chain(pg_test_run_routes()) {
+            let router = Router::new().route(path, route);
+            let uri = path.replace(":test_run_id", "00000000-0000-0000-0000-000000000000");
+            for method in METHODS {
+                let request = Request::builder()
+                    .method(method.clone())
+                    .uri(&uri)
+                    .body(Body::empty())
+                    .unwrap();
+                let response = router.clone().oneshot(request).await.unwrap();
+                if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+                    let path = path.replace(":test_run_id", "{test_run_id}");
+                    operations.insert((path, method.as_str().to_lowercase()));
+                }
+            }
+        }
+        operations
+    }
+
+    #[tokio::test]
+    async fn documents_every_test_run_route() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let documented: BTreeSet<_> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, item)| {
+                METHODS
+                    .iter()
+                    .map(|method| method.as_str().to_lowercase())
+                    .filter(|method| item.get(method).is_some())
+                    .map(|method| (path.clone(), method))
+            })
+            .collect();
+
+        assert_eq!(routed_operations().await, documented);
+    }
+}
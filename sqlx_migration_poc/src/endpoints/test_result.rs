@@ -0,0 +1,76 @@
+use crate::db::PgPools;
+use crate::endpoints::extract::{Json, Path, Query};
+use crate::error::Error;
+use crate::schema::test_result::{NewTestResult, TestResult, TestResultQueryParams};
+use axum::Extension;
+use serde::Serialize;
+use tracing::{debug, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubmittedResults {
+    test_run_id: Uuid,
+    result_count: u64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/test-runs/{test_run_id}/results",
+    operation_id = "submitTestResults",
+    tag = "test-runs",
+    params(("test_run_id" = Uuid, Path, description = "Id of the test run")),
+    request_body = [NewTestResult],
+    responses(
+        (status = 200, description = "Number of results recorded", body = SubmittedResults),
+        (status = 404, description = "No such test run", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid field values", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn submit(
+    pools: Extension<&PgPools>,
+    Path(test_run_id): Path<Uuid>,
+    Json(results): Json<Vec<NewTestResult>>,
+) -> Result<Json<SubmittedResults>, Error> {
+    info!("Received an HTTP 'POST' request at the '/test-runs/{test_run_id}/results' endpoint");
+    debug!("with {} results", results.len());
+    let result_count =
+        TestResult::submit(test_run_id, &results, &mut *pools.acquire().await?).await?;
+
+    Ok(Json(SubmittedResults {
+        test_run_id,
+        result_count,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/test-runs/{test_run_id}/results",
+    operation_id = "listTestResults",
+    tag = "test-runs",
+    params(
+        ("test_run_id" = Uuid, Path, description = "Id of the test run"),
+        TestResultQueryParams,
+    ),
+    responses(
+        (status = 200, description = "The results of the test run", body = [TestResult]),
+        (status = 404, description = "No such test run", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list(
+    pools: Extension<&PgPools>,
+    Path(test_run_id): Path<Uuid>,
+    Query(query_params): Query<TestResultQueryParams>,
+) -> Result<Json<Vec<TestResult>>, Error> {
+    info!("Received an HTTP 'GET' request at the '/test-runs/{test_run_id}/results' endpoint");
+    debug!("with query params: {:?}", query_params);
+    let query_params = &query_params;
+    let test_results = pools
+        .read(|mut conn| async move {
+            TestResult::get_by_test_run(test_run_id, query_params, &mut conn).await
+        })
+        .await?;
+
+    Ok(Json(test_results))
+}
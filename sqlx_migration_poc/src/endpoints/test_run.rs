@@ -1,37 +1,257 @@
-use crate::endpoints::{default_since, default_until, sqlx_err_to_status_code};
-use crate::schema::test_run::TestRun;
-use axum::extract::Query;
-use axum::http::StatusCode;
-use axum::{Extension, Json};
+use crate::endpoints::extract::{Json, Path, Query};
+use crate::endpoints::{default_since, default_until};
+use crate::error::{Error, FieldError};
+use crate::repository::TestRunRepository;
+use crate::schema::test_run::{NewTestRun, TestRun, TestRunPage, TestRunPatch};
+use async_graphql::InputObject;
+use axum::extract::OriginalUri;
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::IntoResponse;
+use axum::Extension;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
-use tracing::{debug, error, info};
+use std::sync::Arc;
+use tracing::{debug, info};
+use utoipa::IntoParams;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoParams, InputObject)]
+#[into_params(parameter_in = Query)]
+#[graphql(name = "TestRunFilter")]
 pub struct TestRunQueryParams {
     pub(crate) test_run_id: Option<Uuid>,
+    pub(crate) build_number: Option<String>,
+    #[graphql(skip)]
     pub(crate) page_num: Option<u32>,
+    #[graphql(skip)]
     pub(crate) per_page: Option<u32>,
     #[serde(default = "default_since")]
+    #[graphql(default_with = "default_since()")]
     pub(crate) since: DateTime<Utc>,
     #[serde(default = "default_until")]
+    #[graphql(default_with = "default_until()")]
     pub(crate) until: DateTime<Utc>,
 }
 
-pub async fn handler(
-    pool: Extension<&PgPool>,
+const DEFAULT_PER_PAGE: u32 = 50;
+const MAX_PER_PAGE: u32 = 500;
+
+static X_TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+impl Default for TestRunQueryParams {
+    fn default() -> Self {
+        Self {
+            test_run_id: None,
+            build_number: None,
+            page_num: None,
+            per_page: None,
+            since: default_since(),
+            until: default_until(),
+        }
+    }
+}
+
+impl TestRunQueryParams {
+    /// The requested page, counting from 1.
+    pub(crate) fn page_num(&self) -> u32 {
+        self.page_num.unwrap_or(1)
+    }
+
+    pub(crate) fn per_page(&self) -> u32 {
+        self.per_page.unwrap_or(DEFAULT_PER_PAGE)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        if self.page_num() == 0 {
+            errors.push(FieldError::new("page_num", "must be at least 1"));
+        }
+        if !(1..=MAX_PER_PAGE).contains(&self.per_page()) {
+            errors.push(FieldError::new(
+                "per_page",
+                format!("must be between 1 and {MAX_PER_PAGE}"),
+            ));
+        }
+        if self.since > self.until {
+            errors.push(FieldError::new("since", "must not be after 'until'"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(errors))
+        }
+    }
+
+    fn with_page_num(&self, page_num: u32) -> Self {
+        Self {
+            page_num: Some(page_num),
+            per_page: Some(self.per_page()),
+            build_number: self.build_number.clone(),
+            ..*self
+        }
+    }
+}
+
+/// Builds the `X-Total-Count` and RFC 8288 `Link` headers for a page of test
+/// runs. The links pin `since` and `until` so that paging through a window
+/// that defaults to "now" stays stable.
+fn pagination_headers(path: &str, query_params: &TestRunQueryParams, total: i64) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(X_TOTAL_COUNT.clone(), HeaderValue::from(total));
+
+    let page_num = query_params.page_num();
+    let last_page = u32::try_from(total)
+        .unwrap_or(u32::MAX)
+        .div_ceil(query_params.per_page())
+        .max(1);
+
+    let mut links = Vec::new();
+    let mut push_link = |rel: &str, page_num: u32| {
+        if let Ok(query) = serde_urlencoded::to_string(query_params.with_page_num(page_num)) {
+            links.push(format!("<{path}?{query}>; rel=\"{rel}\""));
+        }
+    };
+    if page_num < last_page {
+        push_link("next", page_num + 1);
+    }
+    if page_num > 1 {
+        push_link("prev", (page_num - 1).min(last_page));
+    }
+    push_link("first", 1);
+    push_link("last", last_page);
+
+    if let Ok(link) = HeaderValue::from_str(&links.join(", ")) {
+        headers.insert(header::LINK, link);
+    }
+    headers
+}
+
+#[utoipa::path(
+    get,
+    path = "/test-runs",
+    operation_id = "listTestRuns",
+    tag = "test-runs",
+    params(TestRunQueryParams),
+    responses(
+        (status = 200, description = "One page of test runs, newest first", body = [TestRun],
+            headers(
+                ("x-total-count" = i64, description = "Number of matching runs across all pages"),
+                ("link" = String, description = "RFC 8288 links to the next, previous, first and last pages"),
+            )),
+        (status = 400, description = "Malformed request", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid field values", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list(
+    repository: Extension<Arc<dyn TestRunRepository>>,
+    OriginalUri(uri): OriginalUri,
     Query(query_params): Query<TestRunQueryParams>,
-) -> Result<Json<Vec<TestRun>>, StatusCode> {
+) -> Result<impl IntoResponse, Error> {
     info!("Received an HTTP 'GET' request at the '/test-runs' endpoint");
     debug!("with query params: {:?}", query_params);
-    let test_run = TestRun::get_by_query_params(query_params, &pool)
-        .await
-        .map_err(|e| {
-            error!("Error fetching test runs: {e}");
-            sqlx_err_to_status_code(e) // TODO: Return a detailed error
-        })?;
+    let TestRunPage { test_runs, total } = repository.list(&query_params).await?;
+
+    Ok((
+        pagination_headers(uri.path(), &query_params, total),
+        Json(test_runs),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/test-runs",
+    operation_id = "createTestRun",
+    tag = "test-runs",
+    request_body = NewTestRun,
+    responses(
+        (status = 201, description = "The new test run", body = TestRun,
+            headers(("location" = String, description = "URL of the new test run"))),
+        (status = 400, description = "Malformed request", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The build number is already taken", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid field values", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn create(
+    repository: Extension<Arc<dyn TestRunRepository>>,
+    Json(new_test_run): Json<NewTestRun>,
+) -> Result<impl IntoResponse, Error> {
+    info!("Received an HTTP 'POST' request at the '/test-runs' endpoint");
+    debug!("with body: {:?}", new_test_run);
+    let test_run = repository.create(new_test_run).await?;
+
+    let location = format!("/test-runs/{}", test_run.test_run_id);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(test_run),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/test-runs/{test_run_id}",
+    operation_id = "getTestRun",
+    tag = "test-runs",
+    params(("test_run_id" = Uuid, Path, description = "Id of the test run")),
+    responses(
+        (status = 200, description = "The test run", body = TestRun),
+        (status = 404, description = "No such test run", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get(
+    repository: Extension<Arc<dyn TestRunRepository>>,
+    Path(test_run_id): Path<Uuid>,
+) -> Result<Json<TestRun>, Error> {
+    info!("Received an HTTP 'GET' request at the '/test-runs/{test_run_id}' endpoint");
+    let test_run = repository.get(test_run_id).await?;
 
     Ok(Json(test_run))
 }
+
+#[utoipa::path(
+    patch,
+    path = "/test-runs/{test_run_id}",
+    operation_id = "updateTestRun",
+    tag = "test-runs",
+    params(("test_run_id" = Uuid, Path, description = "Id of the test run")),
+    request_body = TestRunPatch,
+    responses(
+        (status = 200, description = "The updated test run", body = TestRun),
+        (status = 404, description = "No such test run", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The build number is already taken", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid field values", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn update(
+    repository: Extension<Arc<dyn TestRunRepository>>,
+    Path(test_run_id): Path<Uuid>,
+    Json(patch): Json<TestRunPatch>,
+) -> Result<Json<TestRun>, Error> {
+    info!("Received an HTTP 'PATCH' request at the '/test-runs/{test_run_id}' endpoint");
+    debug!("with body: {:?}", patch);
+    let test_run = repository.update(test_run_id, patch).await?;
+
+    Ok(Json(test_run))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/test-runs/{test_run_id}",
+    operation_id = "deleteTestRun",
+    tag = "test-runs",
+    params(("test_run_id" = Uuid, Path, description = "Id of the test run")),
+    responses(
+        (status = 204, description = "The test run and its results were deleted"),
+        (status = 404, description = "No such test run", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delete(
+    repository: Extension<Arc<dyn TestRunRepository>>,
+    Path(test_run_id): Path<Uuid>,
+) -> Result<StatusCode, Error> {
+    info!("Received an HTTP 'DELETE' request at the '/test-runs/{test_run_id}' endpoint");
+    repository.delete(test_run_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
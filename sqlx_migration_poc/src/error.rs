@@ -1,11 +1,282 @@
+use crate::request_id;
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::ws::rejection::WebSocketUpgradeRejection;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+use sqlx::error::{DatabaseError, ErrorKind};
+use std::fmt;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+/// What every backend tells the client when a build number is already taken.
+pub(crate) const BUILD_NUMBER_TAKEN: &str = "A test run with this build number already exists";
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error("Invalid configuration: {}", .0.join(", "))]
+    Config(Vec<String>),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    Expired(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    InvalidReport(String),
+
+    #[error("Failed to initialize logging: {0}")]
+    Logging(String),
+
     #[error(transparent)]
     Migration(#[from] sqlx::migrate::MigrateError),
 
-    #[error("Missing environment variable: {0}")]
-    MissingEnvVar(String),
+    #[error("Timed out after {0:?} waiting for the migration lock")]
+    MigrationLockTimeout(std::time::Duration),
+
+    #[error("Invalid request: {message}")]
+    Rejection { status: StatusCode, message: String },
 
     #[error(transparent)]
     Sqlx(#[from] sqlx::Error),
+
+    #[error("{0}")]
+    Unauthenticated(String),
+
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Validation failed: {}", display_field_errors(.0))]
+    Validation(Vec<FieldError>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, ToSchema)]
+pub struct FieldError {
+    pub(crate) field: String,
+    pub(crate) message: String,
+}
+
+impl FieldError {
+    pub(crate) fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+fn display_field_errors(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(FieldError::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// An RFC 9457 problem details body, extended with a machine readable `code`,
+/// the id of the request that failed and any per-field validation errors.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Problem {
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[schema(required = false)]
+    field_errors: Vec<FieldError>,
+}
+
+impl Error {
+    pub(crate) fn status_and_code(&self) -> (StatusCode, &'static str) {
+        match self {
+            Error::Conflict(_) => (StatusCode::CONFLICT, "conflict"),
+            Error::Expired(_) => (StatusCode::GONE, "expired"),
+            Error::Forbidden(_) => (StatusCode::FORBIDDEN, "forbidden"),
+            Error::InvalidReport(_) => (StatusCode::BAD_REQUEST, "invalid_report"),
+            Error::Rejection { status, .. } => (*status, "invalid_request"),
+            Error::Unauthenticated(_) => (StatusCode::UNAUTHORIZED, "unauthenticated"),
+            Error::Unavailable(_) => (StatusCode::SERVICE_UNAVAILABLE, "unavailable"),
+            Error::Validation(_) => (StatusCode::UNPROCESSABLE_ENTITY, "validation_failed"),
+            Error::Sqlx(sqlx::Error::RowNotFound) => (StatusCode::NOT_FOUND, "not_found"),
+            Error::Sqlx(sqlx::Error::Database(e))
+                if matches!(
+                    e.kind(),
+                    ErrorKind::UniqueViolation
+                        | ErrorKind::ForeignKeyViolation
+                        | ErrorKind::NotNullViolation
+                        | ErrorKind::CheckViolation
+                ) =>
+            {
+                (StatusCode::CONFLICT, "conflict")
+            }
+            Error::Sqlx(sqlx::Error::Decode(_) | sqlx::Error::ColumnDecode { .. }) => {
+                (StatusCode::BAD_REQUEST, "decode_failed")
+            }
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error"),
+        }
+    }
+
+    /// Logs the error and returns the message to show the client, which
+    /// leaves out the details of internal errors.
+    pub(crate) fn client_message(&self, status: StatusCode) -> String {
+        if let Error::Unavailable(message) = self {
+            warn!("Request failed: {self}");
+            message.clone()
+        } else if status.is_server_error() {
+            error!("Request failed: {self}");
+            "An internal error occurred".to_string()
+        } else {
+            info!(%status, "Request rejected: {self}");
+            match self {
+                Error::Sqlx(sqlx::Error::RowNotFound) => "Resource not found".to_string(),
+                Error::Sqlx(sqlx::Error::Database(e)) => constraint_message(e.as_ref()).to_string(),
+                Error::Rejection { message, .. } => message.clone(),
+                _ => self.to_string(),
+            }
+        }
+    }
+}
+
+/// What to tell the client about a violated constraint. The database's own
+/// message names tables and constraints, so it is only logged.
+fn constraint_message(e: &dyn DatabaseError) -> &'static str {
+    match e.constraint() {
+        Some("test_run_build_number_key") => BUILD_NUMBER_TAKEN,
+        Some("test_result_test_run_id_fkey") => "The test run does not exist",
+        Some("test_result_test_case_id_fkey") => "The test case does not exist",
+        // SQLite names the constrained column rather than the constraint.
+        None if e.message().contains("test_run.build_number") => BUILD_NUMBER_TAKEN,
+        _ => match e.kind() {
+            ErrorKind::UniqueViolation => "The resource already exists",
+            ErrorKind::ForeignKeyViolation => "A referenced resource does not exist",
+            ErrorKind::NotNullViolation => "A required value is missing",
+            ErrorKind::CheckViolation => "A value is out of range",
+            _ => "The request conflicts with the stored data",
+        },
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let (status, code) = self.status_and_code();
+        let message = self.client_message(status);
+
+        let problem = Problem {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            code,
+            message,
+            request_id: request_id::current(),
+            field_errors: match self {
+                Error::Validation(errors) => errors,
+                _ => Vec::new(),
+            },
+        };
+
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(problem),
+        )
+            .into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+macro_rules! impl_from_rejection {
+    ($($rejection:ty),*) => {
+        $(
+            impl From<$rejection> for Error {
+                fn from(rejection: $rejection) -> Self {
+                    Error::Rejection {
+                        status: rejection.status(),
+                        message: rejection.body_text(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_rejection!(
+    JsonRejection,
+    PathRejection,
+    QueryRejection,
+    WebSocketUpgradeRejection
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_errors_to_problem_responses() {
+        let cases = [
+            (Error::Sqlx(sqlx::Error::RowNotFound), StatusCode::NOT_FOUND),
+            (
+                Error::Validation(vec![FieldError::new("buildNumber", "must not be blank")]),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                Error::Sqlx(sqlx::Error::Decode("bad uuid".into())),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                Error::Sqlx(sqlx::Error::PoolTimedOut),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                Error::Expired("event 7 no longer exists".to_string()),
+                StatusCode::GONE,
+            ),
+            (
+                Error::Unavailable("database is down".to_string()),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                Error::Unauthenticated("missing bearer token".to_string()),
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                Error::Forbidden("token lacks scope 'admin'".to_string()),
+                StatusCode::FORBIDDEN,
+            ),
+        ];
+
+        for (error, status) in cases {
+            let response = error.into_response();
+            assert_eq!(response.status(), status);
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                "application/problem+json"
+            );
+            assert_eq!(
+                response.headers().contains_key(header::WWW_AUTHENTICATE),
+                status == StatusCode::UNAUTHORIZED
+            );
+        }
+    }
 }
@@ -0,0 +1,174 @@
+//! Fans the test run events announced by Postgres out to live subscribers.
+//!
+//! A single `LISTEN` connection is shared by every subscriber and opened when
+//! the first one arrives. If it is lost, or a subscriber falls too far behind,
+//! the affected streams end; clients are expected to reconnect with the id of
+//! the last event they saw and catch up from the `test_run_event` table.
+//!
+//! Events are published in the order of the transactions that recorded them,
+//! each once every older transaction has ended, so that resuming after an
+//! event never skips one committed late. Notifications only prompt a look at
+//! the table, which is also polled while events may be held back by a
+//! transaction that records none.
+
+use crate::error::Error;
+use crate::schema::test_run_event::{EventPosition, TestRunEvent, CHANNEL};
+use futures_util::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// How many events a subscriber may fall behind before its stream is ended.
+const CAPACITY: usize = 256;
+
+/// How often the table is checked for published events between
+/// notifications, while anyone is subscribed.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+type Sender = broadcast::Sender<Arc<TestRunEvent>>;
+
+#[derive(Clone)]
+pub(crate) struct TestRunEvents {
+    pool: &'static PgPool,
+    sender: Arc<Mutex<Option<Sender>>>,
+}
+
+impl TestRunEvents {
+    pub(crate) fn new(pool: &'static PgPool) -> Self {
+        Self {
+            pool,
+            sender: Arc::default(),
+        }
+    }
+
+    /// Events from now on, preceded by those recorded after `last_event_id`
+    /// if one is given.
+    pub(crate) async fn subscribe(
+        &self,
+        last_event_id: Option<i64>,
+    ) -> Result<BoxStream<'static, Result<Arc<TestRunEvent>, Error>>, Error> {
+        // Subscribe before catching up so that nothing recorded in between
+        // is missed. Whatever the catch-up already delivered is skipped.
+        let receiver = self.listen().await?.subscribe();
+        let Some(last_event_id) = last_event_id else {
+            return Ok(live(receiver, EventPosition::default()).boxed());
+        };
+
+        let position = TestRunEvent::position_after(last_event_id, self.pool).await?;
+        let caught_up = Arc::new(std::sync::Mutex::new(position));
+        let missed = {
+            let caught_up = caught_up.clone();
+            TestRunEvent::stream_after(position, self.pool).map(move |event| {
+                let event = event?;
+                *caught_up.lock().unwrap() = event.position();
+                Ok(Arc::new(event))
+            })
+        };
+        let following =
+            stream::once(async move { live(receiver, *caught_up.lock().unwrap()) }).flatten();
+
+        Ok(missed.chain(following).boxed())
+    }
+
+    /// The sender of the shared listener, starting the listener if needed.
+    async fn listen(&self) -> Result<Sender, Error> {
+        let mut slot = self.sender.lock().await;
+        if let Some(sender) = slot.as_ref() {
+            return Ok(sender.clone());
+        }
+
+        let mut listener = PgListener::connect_with(self.pool).await?;
+        listener.listen(CHANNEL).await?;
+        info!(channel = CHANNEL, "Listening for test run events");
+        let published = TestRunEvent::published_position(self.pool).await?;
+
+        let (sender, _) = broadcast::channel(CAPACITY);
+        tokio::spawn(forward(
+            listener,
+            published,
+            self.pool,
+            sender.clone(),
+            self.sender.clone(),
+        ));
+        *slot = Some(sender.clone());
+        Ok(sender)
+    }
+}
+
+/// The events received after `caught_up`, until the subscriber lags behind
+/// or the listener stops.
+fn live(
+    receiver: broadcast::Receiver<Arc<TestRunEvent>>,
+    caught_up: EventPosition,
+) -> BoxStream<'static, Result<Arc<TestRunEvent>, Error>> {
+    stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if event.position() <= caught_up => continue,
+                Ok(event) => return Some((Ok(event), receiver)),
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Subscriber fell behind on test run events");
+                    return None;
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Passes the events published after `published` on to the subscribers,
+/// looking for more whenever an event is announced. When the connection is
+/// lost the listener is discarded, which ends every subscription, and the
+/// next subscriber starts a new one.
+async fn forward(
+    mut listener: PgListener,
+    mut published: EventPosition,
+    pool: &'static PgPool,
+    sender: Sender,
+    slot: Arc<Mutex<Option<Sender>>>,
+) {
+    loop {
+        match tokio::time::timeout(POLL_INTERVAL, listener.try_recv()).await {
+            Ok(Ok(Some(_))) => {}
+            Ok(Ok(None)) => {
+                warn!("Lost the connection listening for test run events");
+                break;
+            }
+            Ok(Err(e)) => {
+                warn!("Stopped listening for test run events: {e}");
+                break;
+            }
+            Err(_) if sender.receiver_count() == 0 => continue,
+            Err(_) => {}
+        }
+
+        let events: Vec<TestRunEvent> = match TestRunEvent::stream_after(published, pool)
+            .try_collect()
+            .await
+        {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Failed to load test run events: {e}");
+                continue;
+            }
+        };
+        for event in events {
+            debug!(event_id = event.event_id, "Forwarding test run event");
+            published = event.position();
+            let _ = sender.send(Arc::new(event));
+        }
+    }
+
+    let mut slot = slot.lock().await;
+    if slot
+        .as_ref()
+        .is_some_and(|current| current.same_channel(&sender))
+    {
+        *slot = None;
+    }
+}
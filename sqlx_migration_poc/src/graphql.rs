@@ -0,0 +1,327 @@
+//! A read-only GraphQL view of test runs, their results and the analytics
+//! aggregates, so that a dashboard can fetch all of them in one round trip.
+//! Results are batch loaded per request, so listing many runs along with
+//! their results costs two queries rather than one per run, and queries are
+//! limited in how many runs and results they may ask for. Everything is
+//! read from the replica when there is a healthy one.
+
+use crate::db::PgPools;
+use crate::endpoints::analytics::AnalyticsQueryParams;
+use crate::endpoints::test_run::TestRunQueryParams;
+use crate::error::{Error, FieldError};
+use crate::request_id;
+use crate::schema::analytics::{FailureRate, FlakyTest, SlowTest};
+use crate::schema::test_result::{TestResult, TestResultQueryParams, TestStatus};
+use crate::schema::test_run::{TestRun, TestRunCursor};
+use async_graphql::connection::{self, Connection, Edge, OpaqueCursor};
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object, Schema,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+const MAX_DEPTH: usize = 10;
+/// The most a query may cost, where every field costs one and a list of
+/// runs costs its page size times what is asked of each run.
+const MAX_COMPLEXITY: usize = 5_000;
+/// The cost of the results of one run on top of the fields asked of them.
+const RESULTS_COMPLEXITY: usize = 20;
+
+pub type ApiSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+type TestRunConnection = Connection<OpaqueCursor<TestRunCursor>, TestRun, TestRunConnectionFields>;
+
+pub(crate) fn schema(pools: &'static PgPools) -> ApiSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(pools)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// The per-request loader of test results. A new one is needed for every
+/// request so that its cache never outlives the request.
+pub(crate) fn results_loader(pools: &'static PgPools) -> DataLoader<ResultsLoader> {
+    DataLoader::new(ResultsLoader { pools }, tokio::spawn)
+}
+
+impl ErrorExtensions for Error {
+    fn extend(&self) -> async_graphql::Error {
+        let (status, code) = self.status_and_code();
+        async_graphql::Error::new(self.client_message(status)).extend_with(|_, extensions| {
+            extensions.set("code", code);
+            extensions.set("status", status.as_u16());
+            if let Some(request_id) = request_id::current() {
+                extensions.set("requestId", request_id);
+            }
+            if let Error::Validation(errors) = self {
+                if let Ok(field_errors) = async_graphql::to_value(errors) {
+                    extensions.set("fieldErrors", field_errors);
+                }
+            }
+        })
+    }
+}
+
+fn pools(ctx: &Context<'_>) -> &'static PgPools {
+    ctx.data_unchecked::<&'static PgPools>()
+}
+
+/// The results of a test run to load, filtered by status and suite.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ResultsKey {
+    test_run_id: Uuid,
+    status: Option<TestStatus>,
+    suite_name: Option<String>,
+}
+
+/// Loads the results of many test runs with a single query per filter.
+pub struct ResultsLoader {
+    pools: &'static PgPools,
+}
+
+impl Loader<ResultsKey> for ResultsLoader {
+    type Value = Vec<TestResult>;
+    type Error = Arc<Error>;
+
+    async fn load(
+        &self,
+        keys: &[ResultsKey],
+    ) -> Result<HashMap<ResultsKey, Self::Value>, Self::Error> {
+        let mut test_run_ids: HashMap<(Option<TestStatus>, Option<String>), Vec<Uuid>> =
+            HashMap::new();
+        for key in keys {
+            test_run_ids
+                .entry((key.status, key.suite_name.clone()))
+                .or_default()
+                .push(key.test_run_id);
+        }
+
+        let mut results: HashMap<ResultsKey, Vec<TestResult>> =
+            keys.iter().map(|key| (key.clone(), Vec::new())).collect();
+        for ((status, suite_name), test_run_ids) in test_run_ids {
+            let query_params = TestResultQueryParams {
+                status,
+                suite_name: suite_name.clone(),
+                case_name: None,
+            };
+            let loaded = self
+                .pools
+                .read(|mut conn| {
+                    let (test_run_ids, query_params) = (&test_run_ids, &query_params);
+                    async move {
+                        TestResult::get_by_test_runs(test_run_ids, query_params, &mut conn).await
+                    }
+                })
+                .await?;
+            for result in loaded {
+                let key = ResultsKey {
+                    test_run_id: result.test_run_id,
+                    status,
+                    suite_name: suite_name.clone(),
+                };
+                results.entry(key).or_default().push(result);
+            }
+        }
+        Ok(results)
+    }
+}
+
+#[ComplexObject]
+impl TestRun {
+    /// The results recorded for this run, ordered by suite and case name.
+    #[graphql(complexity = "RESULTS_COMPLEXITY + child_complexity")]
+    async fn results(
+        &self,
+        ctx: &Context<'_>,
+        status: Option<TestStatus>,
+        suite_name: Option<String>,
+    ) -> async_graphql::Result<Vec<TestResult>> {
+        let key = ResultsKey {
+            test_run_id: self.test_run_id,
+            status,
+            suite_name,
+        };
+        Ok(ctx
+            .data_unchecked::<DataLoader<ResultsLoader>>()
+            .load_one(key)
+            .await
+            .map_err(|e| e.as_ref().extend())?
+            .unwrap_or_default())
+    }
+}
+
+/// Fields of a test run connection besides its edges and page info.
+pub struct TestRunConnectionFields {
+    filter: TestRunQueryParams,
+}
+
+#[Object]
+impl TestRunConnectionFields {
+    /// The number of runs matching the filter across all pages.
+    async fn total_count(&self, ctx: &Context<'_>) -> async_graphql::Result<i64> {
+        pools(ctx)
+            .read(|mut conn| async move { TestRun::count(&self.filter, &mut conn).await })
+            .await
+            .map_err(|e| e.extend())
+    }
+}
+
+/// The number of runs to fetch and whether they are the last ones before the
+/// `before` cursor, rather than the first ones after the `after` cursor.
+fn page_size(first: Option<usize>, last: Option<usize>) -> Result<(usize, bool), Error> {
+    let (field, size, from_end) = match (first, last) {
+        (Some(_), Some(_)) => {
+            return Err(Error::Validation(vec![FieldError::new(
+                "last",
+                "must not be combined with 'first'",
+            )]))
+        }
+        (None, Some(last)) => ("last", last, true),
+        (first, None) => ("first", first.unwrap_or(DEFAULT_PAGE_SIZE), false),
+    };
+
+    if (1..=MAX_PAGE_SIZE).contains(&size) {
+        Ok((size, from_end))
+    } else {
+        Err(Error::Validation(vec![FieldError::new(
+            field,
+            format!("must be between 1 and {MAX_PAGE_SIZE}"),
+        )]))
+    }
+}
+
+/// The most runs a page asked for with `first` or `last` may hold.
+fn page_complexity(first: Option<i32>, last: Option<i32>) -> usize {
+    first.or(last).map_or(DEFAULT_PAGE_SIZE, |size| {
+        size.clamp(0, MAX_PAGE_SIZE as i32) as usize
+    })
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Test runs matching `filter`, newest first.
+    #[graphql(complexity = "page_complexity(first, last) * child_complexity")]
+    async fn test_runs(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "TestRunQueryParams::default()")] filter: TestRunQueryParams,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> async_graphql::Result<TestRunConnection> {
+        let pools = pools(ctx);
+        connection::query(
+            after,
+            before,
+            first,
+            last,
+            |after: Option<OpaqueCursor<TestRunCursor>>,
+             before: Option<OpaqueCursor<TestRunCursor>>,
+             first,
+             last| async move {
+                let (size, from_end) = page_size(first, last).map_err(|e| e.extend())?;
+                let (after_cursor, before_cursor) =
+                    (after.as_deref().copied(), before.as_deref().copied());
+                let query_params = &filter;
+                let mut test_runs = pools
+                    .read(|mut conn| async move {
+                        TestRun::get_between(
+                            query_params,
+                            after_cursor,
+                            before_cursor,
+                            size as i64 + 1,
+                            from_end,
+                            &mut conn,
+                        )
+                        .await
+                    })
+                    .await
+                    .map_err(|e| e.extend())?;
+
+                let has_more = test_runs.len() > size;
+                if has_more && from_end {
+                    test_runs.remove(0);
+                } else {
+                    test_runs.truncate(size);
+                }
+                let (has_previous_page, has_next_page) = if from_end {
+                    (has_more, before.is_some())
+                } else {
+                    (after.is_some(), has_more)
+                };
+
+                let mut connection = Connection::with_additional_fields(
+                    has_previous_page,
+                    has_next_page,
+                    TestRunConnectionFields { filter },
+                );
+                connection
+                    .edges
+                    .extend(test_runs.into_iter().map(|test_run| {
+                        Edge::new(OpaqueCursor(TestRunCursor::from(&test_run)), test_run)
+                    }));
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
+    async fn test_run(
+        &self,
+        ctx: &Context<'_>,
+        test_run_id: Uuid,
+    ) -> async_graphql::Result<Option<TestRun>> {
+        match pools(ctx)
+            .read(|mut conn| async move { TestRun::get_by_id(test_run_id, &mut conn).await })
+            .await
+        {
+            Ok(test_run) => Ok(Some(test_run)),
+            Err(Error::Sqlx(sqlx::Error::RowNotFound)) => Ok(None),
+            Err(e) => Err(e.extend()),
+        }
+    }
+
+    async fn failure_rates(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "AnalyticsQueryParams::default()")] filter: AnalyticsQueryParams,
+    ) -> async_graphql::Result<Vec<FailureRate>> {
+        let filter = &filter;
+        pools(ctx)
+            .read(|mut conn| async move { FailureRate::get(filter, &mut conn).await })
+            .await
+            .map_err(|e| e.extend())
+    }
+
+    async fn flaky_tests(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "AnalyticsQueryParams::default()")] filter: AnalyticsQueryParams,
+    ) -> async_graphql::Result<Vec<FlakyTest>> {
+        let filter = &filter;
+        pools(ctx)
+            .read(|mut conn| async move { FlakyTest::get(filter, &mut conn).await })
+            .await
+            .map_err(|e| e.extend())
+    }
+
+    async fn slowest_tests(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "AnalyticsQueryParams::default()")] filter: AnalyticsQueryParams,
+    ) -> async_graphql::Result<Vec<SlowTest>> {
+        let filter = &filter;
+        pools(ctx)
+            .read(|mut conn| async move { SlowTest::get(filter, &mut conn).await })
+            .await
+            .map_err(|e| e.extend())
+    }
+}
@@ -1,60 +1,216 @@
-use std::env;
-use tracing::info;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
+use axum::Router;
+use config::{Config, LogFileConfig, LogFormat, LoggingConfig};
+use error::Error;
+use repository::Storage;
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use std::fs;
+use std::future::{Future, IntoFuture};
+use std::io;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::signal;
+use tokio_util::sync::CancellationToken;
 use tracing::subscriber::set_global_default;
+use tracing::{info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::{fmt, registry, EnvFilter};
+use tracing_subscriber::{fmt, registry, Layer};
 
+mod auth;
+pub mod cli;
+pub mod config;
 pub mod db;
 mod endpoints;
 mod error;
+mod events;
+mod graphql;
+mod metrics;
+mod report;
+pub mod repository;
+mod request_id;
+pub mod retention;
 pub mod schema;
 
-pub fn init_logging_from_env() {
-    let json_layer = fmt::layer()
-        .json()
-        .flatten_event(true)
-        .with_file(true)
-        .with_line_number(true)
-        .with_thread_ids(true);
+pub use endpoints::create_routes;
+
+/// Cancelled as soon as [`serve`] starts shutting down, so that handlers of
+/// requests that never finish on their own, like event streams, can wrap
+/// them up. Only present on requests handled by [`serve`].
+#[derive(Clone)]
+pub(crate) struct Draining(pub(crate) CancellationToken);
+
+/// Keeps the background thread writing file logs alive. Events logged after
+/// it has been dropped are lost, so hold it until the process exits.
+#[must_use]
+pub struct LoggingGuard {
+    _guard: Option<WorkerGuard>,
+}
+
+fn log_file_appender(config: &LogFileConfig) -> io::Result<BasicRollingFileAppender> {
+    let mut condition = RollingConditionBasic::new();
+    if config.rotate_daily {
+        condition = condition.daily();
+    }
+    if let Some(max_size_mb) = config.max_size_mb {
+        condition = condition.max_size(max_size_mb * 1024 * 1024);
+    }
+    if let Some(dir) = config
+        .path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir)?;
+    }
+
+    BasicRollingFileAppender::new(&config.path, condition, config.max_files)
+}
+
+pub fn init_logging(config: &LoggingConfig) -> Result<LoggingGuard, Error> {
+    let filter = config.env_filter()?;
+
+    let (writer, guard) = match &config.file {
+        Some(file) => {
+            let appender = log_file_appender(file).map_err(|e| {
+                Error::Logging(format!(
+                    "failed to open log file '{}': {e}",
+                    file.path.display()
+                ))
+            })?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(io::stdout), None),
+    };
 
-    let compact_layer = fmt::layer()
-        .compact()
+    let layer = fmt::layer()
+        .with_writer(writer)
+        .with_ansi(config.file.is_none())
         .with_file(true)
         .with_line_number(true)
         .with_thread_ids(true);
+    let layer = match config.format {
+        LogFormat::Json => layer.json().flatten_event(true).boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Pretty => layer.pretty().boxed(),
+    };
+
+    set_global_default(registry().with(filter).with(layer))
+        .map_err(|e| Error::Logging(e.to_string()))?;
+
+    Ok(LoggingGuard { _guard: guard })
+}
+
+/// Resolves once the process receives SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
 
-    let error_message = "Failed to initialize logging";
+/// Serves `router` until `shutdown` resolves, then stops accepting connections
+/// and waits up to `drain_timeout` for in-flight requests to finish. Requests
+/// still running after that are cancelled with a 503.
+pub async fn serve<F>(
+    listener: TcpListener,
+    router: Router,
+    shutdown: F,
+    drain_timeout: Duration,
+) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let draining = CancellationToken::new();
+    let expired = CancellationToken::new();
 
-    if let Ok(logger) = env::var("LOGGER") {
-        if logger == "Default" {
-            set_global_default(
-                registry()
-                    .with(EnvFilter::from_default_env())
-                    .with(compact_layer),
-            )
-            .expect(error_message);
+    let signal = {
+        let draining = draining.clone();
+        async move {
+            shutdown.await;
+            info!(?drain_timeout, "Shutting down, draining in-flight requests");
+            draining.cancel();
         }
-    } else {
-        set_global_default(
-            registry()
-                .with(EnvFilter::from_default_env())
-                .with(json_layer),
-        )
-        .expect(error_message);
+    };
+    let router = {
+        let draining = draining.clone();
+        let expired = expired.clone();
+        router.layer(middleware::from_fn(
+            move |mut request: Request, next: Next| {
+                request.extensions_mut().insert(Draining(draining.clone()));
+                let expired = expired.clone();
+                async move {
+                    tokio::select! {
+                        response = next.run(request) => response,
+                        _ = expired.cancelled() => StatusCode::SERVICE_UNAVAILABLE.into_response(),
+                    }
+                }
+            },
+        ))
+    };
+
+    let server = axum::serve(listener, router)
+        .with_graceful_shutdown(signal)
+        .into_future();
+    tokio::pin!(server);
+
+    tokio::select! {
+        result = &mut server => return result,
+        _ = async {
+            draining.cancelled().await;
+            tokio::time::sleep(drain_timeout).await;
+        } => {}
     }
+
+    warn!("Drain timeout elapsed, cancelling remaining requests");
+    expired.cancel();
+    server.await
 }
 
-pub async fn start_server() -> Result<(), Box<dyn std::error::Error>> {
-    let pool = db::get_pool().await?;
+pub async fn start_server(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let storage = Storage::connect(config).await?;
+    let retention = storage
+        .pg_pool()
+        .map(|pool| tokio::spawn(retention::run(pool, config.retention.clone())));
+
+    let router = endpoints::create_routes(&storage, config);
+    let listener = TcpListener::bind(config.server.bind_address).await?;
 
-    let router = endpoints::create_routes(pool);
-    let port = 3000;
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
-        .await
-        .unwrap();
+    info!("Listening on: {}", listener.local_addr()?);
+    serve(
+        listener,
+        router,
+        shutdown_signal(),
+        config.server.shutdown_timeout(),
+    )
+    .await?;
 
-    info!("Listening on port: {}", port);
-    axum::serve(listener, router).await.unwrap();
+    if let Some(retention) = retention {
+        retention.abort();
+    }
+    info!("Closing storage connections");
+    storage.close().await;
+    info!("Server stopped");
 
     Ok(())
 }
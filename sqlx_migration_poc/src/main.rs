@@ -1,12 +1,34 @@
-use sqlx_migration_poc::{init_logging_from_env, start_server};
+use clap::Parser;
+use sqlx_migration_poc::cli::{self, Cli};
+use sqlx_migration_poc::config::Config;
+use sqlx_migration_poc::init_logging;
+use std::process::ExitCode;
 use tracing::error;
 
 #[tokio::main]
-async fn main() {
-    init_logging_from_env();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let config = match Config::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(2);
+        }
+    };
 
-    if let Err(e) = start_server().await {
-        error!("An error occurred when starting server: {}", e);
-        std::process::exit(1);
+    let _logging_guard = match init_logging(&config.logging) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match cli::run(cli.command, &config).await {
+        Ok(code) => code,
+        Err(e) => {
+            error!("An error occurred: {}", e);
+            ExitCode::FAILURE
+        }
     }
 }
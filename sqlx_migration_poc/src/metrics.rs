@@ -0,0 +1,225 @@
+//! Request instrumentation and the Prometheus metrics served at `/metrics`.
+
+use crate::repository::Storage;
+use crate::request_id::X_REQUEST_ID;
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::{Database, Pool};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::{info, info_span, Instrument};
+
+/// The `route` label of requests that matched no route, so that probing for
+/// random paths cannot create unbounded label values.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    pool_connections: IntGaugeVec,
+    pool_idle_connections: IntGaugeVec,
+    pool_max_connections: IntGaugeVec,
+    pool_acquire_wait: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> prometheus::Result<Self> {
+        let metrics = Metrics {
+            registry: Registry::new(),
+            requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests handled"),
+                &["method", "route", "status"],
+            )?,
+            request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Time until the response headers were ready",
+                ),
+                &["method", "route"],
+            )?,
+            pool_connections: IntGaugeVec::new(
+                Opts::new(
+                    "db_pool_connections",
+                    "Connections currently open in the DB pool",
+                ),
+                &["pool"],
+            )?,
+            pool_idle_connections: IntGaugeVec::new(
+                Opts::new("db_pool_idle_connections", "Open DB connections not in use"),
+                &["pool"],
+            )?,
+            pool_max_connections: IntGaugeVec::new(
+                Opts::new(
+                    "db_pool_max_connections",
+                    "Most connections the DB pool will open",
+                ),
+                &["pool"],
+            )?,
+            pool_acquire_wait: HistogramVec::new(
+                HistogramOpts::new(
+                    "db_pool_acquire_wait_seconds",
+                    "Time spent waiting for a connection from the DB pool",
+                ),
+                &["pool"],
+            )?,
+        };
+
+        metrics
+            .registry
+            .register(Box::new(metrics.requests.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.request_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.pool_connections.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.pool_idle_connections.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.pool_max_connections.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.pool_acquire_wait.clone()))?;
+
+        Ok(metrics)
+    }
+}
+
+static METRICS: LazyLock<Metrics> =
+    LazyLock::new(|| Metrics::new().expect("metric definitions are valid"));
+
+/// Middleware that runs each request in a span carrying its method, route and
+/// request id, logs its status and latency once the response is ready, and
+/// records both in the request metrics. Must run inside
+/// [`crate::request_id::propagate`] so that the request id is set.
+pub(crate) async fn track(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED_ROUTE, MatchedPath::as_str)
+        .to_string();
+    let request_id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+    let span = info_span!(
+        "request",
+        %method,
+        %route,
+        request_id,
+        status = Empty,
+        latency_ms = Empty,
+    );
+
+    let started = Instant::now();
+    let response = next.run(request).instrument(span.clone()).await;
+    let latency = started.elapsed();
+    let status = response.status();
+
+    span.record("status", status.as_u16());
+    span.record("latency_ms", latency.as_secs_f64() * 1000.0);
+    span.in_scope(|| info!("Finished request"));
+
+    METRICS
+        .requests
+        .with_label_values(&[method.as_str(), &route, status.as_str()])
+        .inc();
+    METRICS
+        .request_duration
+        .with_label_values(&[method.as_str(), &route])
+        .observe(latency.as_secs_f64());
+
+    response
+}
+
+/// Records the connection counts of `pool`, labelled with its `name`.
+fn record_pool_stats<DB: Database>(name: &str, pool: &Pool<DB>) {
+    let labels = &[name];
+    METRICS
+        .pool_connections
+        .with_label_values(labels)
+        .set(i64::from(pool.size()));
+    METRICS
+        .pool_idle_connections
+        .with_label_values(labels)
+        .set(pool.num_idle() as i64);
+    METRICS
+        .pool_max_connections
+        .with_label_values(labels)
+        .set(i64::from(pool.options().get_max_connections()));
+}
+
+/// Records that taking a connection from the pool named `pool` took `wait`,
+/// whether or not one was handed out in the end.
+pub(crate) fn observe_acquire_wait(pool: &str, wait: Duration) {
+    METRICS
+        .pool_acquire_wait
+        .with_label_values(&[pool])
+        .observe(wait.as_secs_f64());
+}
+
+/// Samples the connection pools of `storage`, if it has any, and renders every
+/// metric in the Prometheus text format.
+pub(crate) fn render(storage: &Storage) -> String {
+    match storage {
+        Storage::Postgres(pools) => {
+            record_pool_stats("primary", pools.primary());
+            if let Some(replica) = pools.replica() {
+                record_pool_stats("replica", replica);
+            }
+        }
+        Storage::Sqlite(pool) => record_pool_stats("primary", pool),
+        Storage::Memory(_) => {}
+    }
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&METRICS.registry.gather(), &mut buffer)
+        .expect("metrics encode as text");
+    String::from_utf8(buffer).expect("the text format is UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::create_routes;
+    use crate::repository::Storage;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Request, StatusCode};
+    use axum::response::Response;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    async fn get(router: &Router, uri: &str) -> Response {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn counts_requests_by_route() {
+        let router = create_routes(&Storage::Memory(Default::default()), &Config::default());
+        get(&router, "/test-runs/00000000-0000-0000-0000-000000000000").await;
+        get(&router, "/no-such-route").await;
+
+        let response = get(&router, "/metrics").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains(
+            "http_requests_total{method=\"GET\",route=\"/test-runs/:test_run_id\",status=\"404\"}"
+        ));
+        assert!(
+            text.contains("http_requests_total{method=\"GET\",route=\"unmatched\",status=\"404\"}")
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use super::{invalid_report, ResultCollector};
+use crate::error::Error;
+use crate::schema::test_result::{NewTestResult, TestStatus};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fmt::Display;
+use tokio::io::AsyncBufRead;
+
+fn invalid(e: impl Display) -> Error {
+    invalid_report("JUnit XML", e)
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, Error> {
+    element
+        .try_get_attribute(name)
+        .map_err(invalid)?
+        .map(|attribute| attribute.unescape_value().map(|value| value.into_owned()))
+        .transpose()
+        .map_err(invalid)
+}
+
+/// A `<testcase>` whose closing tag has not been read yet.
+struct PendingCase {
+    result: NewTestResult,
+    capture_message: bool,
+}
+
+impl PendingCase {
+    fn start(element: &BytesStart, suite_name: &str) -> Result<Self, Error> {
+        let case_name = attribute(element, "name")?
+            .ok_or_else(|| invalid("<testcase> without a 'name' attribute"))?;
+        let suite_name = attribute(element, "classname")?
+            .filter(|classname| !classname.is_empty())
+            .unwrap_or_else(|| suite_name.to_string());
+        let duration_ms = match attribute(element, "time")? {
+            Some(time) => {
+                let seconds: f64 = time
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid time '{time}' for '{case_name}'")))?;
+                (seconds * 1000.0).round() as i64
+            }
+            None => 0,
+        };
+
+        Ok(Self {
+            result: NewTestResult {
+                suite_name,
+                case_name,
+                status: TestStatus::Passed,
+                duration_ms,
+                failure_message: None,
+                retries: 0,
+            },
+            capture_message: false,
+        })
+    }
+
+    /// Applies an outcome element nested in the case, e.g. `<failure>`.
+    fn outcome(&mut self, element: &BytesStart) -> Result<(), Error> {
+        let status = match element.local_name().as_ref() {
+            b"failure" => TestStatus::Failed,
+            b"error" => TestStatus::Error,
+            b"skipped" => TestStatus::Skipped,
+            // Surefire records each failed attempt of a re-run test separately.
+            b"flakyFailure" | b"flakyError" | b"rerunFailure" | b"rerunError" => {
+                self.result.retries += 1;
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+
+        self.result.status = status;
+        self.result.failure_message = attribute(element, "message")?;
+        self.capture_message =
+            status != TestStatus::Skipped && self.result.failure_message.is_none();
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.capture_message {
+            let text = text.trim();
+            if !text.is_empty() {
+                self.result.failure_message = Some(text.to_string());
+            }
+            self.capture_message = false;
+        }
+    }
+}
+
+pub(super) async fn parse<R>(reader: R, default_suite: &str) -> Result<Vec<NewTestResult>, Error>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut reader = Reader::from_reader(reader);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut suites: Vec<String> = Vec::new();
+    let mut pending: Option<PendingCase> = None;
+    let mut collector = ResultCollector::default();
+
+    loop {
+        let event = reader
+            .read_event_into_async(&mut buf)
+            .await
+            .map_err(invalid)?;
+        let is_empty = matches!(event, Event::Empty(_));
+
+        match event {
+            Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                b"testsuite" if !is_empty => {
+                    let name = attribute(&element, "name")?.filter(|name| !name.is_empty());
+                    let parent = suites.last().map_or(default_suite, String::as_str);
+                    suites.push(name.unwrap_or_else(|| parent.to_string()));
+                }
+                b"testcase" => {
+                    let suite_name = suites.last().map_or(default_suite, String::as_str);
+                    let case = PendingCase::start(&element, suite_name)?;
+                    if is_empty {
+                        collector.push(case.result);
+                    } else {
+                        pending = Some(case);
+                    }
+                }
+                _ => {
+                    if let Some(case) = pending.as_mut() {
+                        case.outcome(&element)?;
+                        if is_empty {
+                            case.capture_message = false;
+                        }
+                    }
+                }
+            },
+            Event::Text(text) => {
+                if let Some(case) = pending.as_mut() {
+                    case.text(&text.unescape().map_err(invalid)?);
+                }
+            }
+            Event::CData(data) => {
+                if let Some(case) = pending.as_mut() {
+                    case.text(&String::from_utf8_lossy(&data));
+                }
+            }
+            Event::End(element) => match element.local_name().as_ref() {
+                b"testsuite" => {
+                    suites.pop();
+                }
+                b"testcase" => {
+                    if let Some(case) = pending.take() {
+                        collector.push(case.result);
+                    }
+                }
+                _ => {
+                    if let Some(case) = pending.as_mut() {
+                        case.capture_message = false;
+                    }
+                }
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(collector.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn parses_junit_report() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <testsuites>
+              <testsuite name="api" tests="4">
+                <testcase classname="api.runs" name="creates" time="0.25"/>
+                <testcase classname="api.runs" name="lists" time="1.5">
+                  <failure message="expected 200, got 500">stack trace</failure>
+                </testcase>
+                <testcase name="crashes"><error><![CDATA[panicked at 'boom']]></error></testcase>
+                <testcase name="ignored"><skipped/></testcase>
+                <testcase classname="api.runs" name="creates" time="0.5">
+                  <flakyFailure message="timed out"/>
+                </testcase>
+              </testsuite>
+            </testsuites>"#;
+
+        let results = parse(xml.as_bytes(), "default").await.unwrap();
+
+        assert_eq!(
+            results,
+            vec![
+                NewTestResult {
+                    suite_name: "api.runs".to_string(),
+                    case_name: "creates".to_string(),
+                    status: TestStatus::Passed,
+                    duration_ms: 500,
+                    failure_message: None,
+                    retries: 2,
+                },
+                NewTestResult {
+                    suite_name: "api.runs".to_string(),
+                    case_name: "lists".to_string(),
+                    status: TestStatus::Failed,
+                    duration_ms: 1500,
+                    failure_message: Some("expected 200, got 500".to_string()),
+                    retries: 0,
+                },
+                NewTestResult {
+                    suite_name: "api".to_string(),
+                    case_name: "crashes".to_string(),
+                    status: TestStatus::Error,
+                    duration_ms: 0,
+                    failure_message: Some("panicked at 'boom'".to_string()),
+                    retries: 0,
+                },
+                NewTestResult {
+                    suite_name: "api".to_string(),
+                    case_name: "ignored".to_string(),
+                    status: TestStatus::Skipped,
+                    duration_ms: 0,
+                    failure_message: None,
+                    retries: 0,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_xml() {
+        let xml = r#"<testsuite name="api"><testcase name="a" time="soon"/></testsuite>"#;
+        assert!(matches!(
+            parse(xml.as_bytes(), "default").await,
+            Err(Error::InvalidReport(_))
+        ));
+
+        let xml = r#"<testsuite name="api"><testcase name="a"></testsuite>"#;
+        assert!(matches!(
+            parse(xml.as_bytes(), "default").await,
+            Err(Error::InvalidReport(_))
+        ));
+    }
+}
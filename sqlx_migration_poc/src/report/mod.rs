@@ -0,0 +1,83 @@
+//! Streaming parsers for the test reports CI systems produce. Reports are read
+//! incrementally from an [`AsyncBufRead`], so a document never has to be held
+//! in memory as a whole.
+
+use crate::error::Error;
+use crate::schema::test_result::NewTestResult;
+use axum::http::{header, HeaderMap};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use tokio::io::AsyncBufRead;
+use utoipa::ToSchema;
+
+mod junit;
+mod tap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Junit,
+    Tap,
+}
+
+impl ReportFormat {
+    /// Guesses the format from a request's `Content-Type`, if it names one.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+        match content_type.split(';').next()?.trim() {
+            "application/xml" | "text/xml" | "application/junit+xml" => Some(ReportFormat::Junit),
+            "text/x-tap" | "application/x-tap" => Some(ReportFormat::Tap),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a report into test results. Cases without a suite of their own are
+/// filed under `default_suite`.
+pub(crate) async fn parse<R>(
+    format: ReportFormat,
+    reader: R,
+    default_suite: &str,
+) -> Result<Vec<NewTestResult>, Error>
+where
+    R: AsyncBufRead + Unpin,
+{
+    match format {
+        ReportFormat::Junit => junit::parse(reader, default_suite).await,
+        ReportFormat::Tap => tap::parse(reader, default_suite).await,
+    }
+}
+
+fn invalid_report(format: &str, e: impl Display) -> Error {
+    Error::InvalidReport(format!("invalid {format} report: {e}"))
+}
+
+/// Accumulates parsed results. A case that shows up more than once is treated
+/// as having been re-run: the last outcome wins and the earlier executions are
+/// counted as retries.
+#[derive(Default)]
+struct ResultCollector {
+    results: Vec<NewTestResult>,
+    positions: HashMap<(String, String), usize>,
+}
+
+impl ResultCollector {
+    fn push(&mut self, result: NewTestResult) {
+        let key = (result.suite_name.clone(), result.case_name.clone());
+        match self.positions.get(&key) {
+            Some(&i) => {
+                let retries = self.results[i].retries + 1 + result.retries;
+                self.results[i] = NewTestResult { retries, ..result };
+            }
+            None => {
+                self.positions.insert(key, self.results.len());
+                self.results.push(result);
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<NewTestResult> {
+        self.results
+    }
+}
@@ -0,0 +1,212 @@
+use super::{invalid_report, ResultCollector};
+use crate::error::Error;
+use crate::schema::test_result::{NewTestResult, TestStatus};
+use std::fmt::Display;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+fn invalid(e: impl Display) -> Error {
+    invalid_report("TAP", e)
+}
+
+/// Parses a test point line such as `not ok 3 - parses input # TODO later`.
+/// Returns `None` for anything that is not a test point.
+fn parse_test_point(line: &str, next_number: u32, suite_name: &str) -> Option<NewTestResult> {
+    let (ok, rest) = if let Some(rest) = line.strip_prefix("not ok") {
+        (false, rest)
+    } else {
+        (true, line.strip_prefix("ok")?)
+    };
+    if !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+
+    let rest = rest.trim_start();
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let number = rest[..digits].parse().unwrap_or(next_number);
+    let rest = rest[digits..].trim_start();
+    let rest = rest.strip_prefix('-').unwrap_or(rest);
+
+    let (description, directive) = match rest
+        .find(" #")
+        .or_else(|| rest.strip_prefix('#').map(|_| 0))
+    {
+        Some(i) => (
+            &rest[..i],
+            Some(rest[i..].trim_start().trim_start_matches('#').trim()),
+        ),
+        None => (rest, None),
+    };
+    let description = description.trim();
+
+    let directive = directive.map(|directive| {
+        let keyword = directive.split_whitespace().next().unwrap_or_default();
+        (
+            keyword.to_ascii_uppercase(),
+            directive[keyword.len()..].trim(),
+        )
+    });
+    let (status, failure_message) = match directive {
+        Some((keyword, reason)) if keyword == "SKIP" || keyword == "TODO" => {
+            let reason = Some(reason.to_string()).filter(|reason| !reason.is_empty());
+            (TestStatus::Skipped, reason)
+        }
+        _ if ok => (TestStatus::Passed, None),
+        _ => (TestStatus::Failed, None),
+    };
+
+    Some(NewTestResult {
+        suite_name: suite_name.to_string(),
+        case_name: if description.is_empty() {
+            format!("test {number}")
+        } else {
+            description.to_string()
+        },
+        status,
+        duration_ms: 0,
+        failure_message,
+        retries: 0,
+    })
+}
+
+/// Picks the failure message and duration out of a YAML diagnostic block.
+fn apply_diagnostics(result: &mut NewTestResult, lines: &[String]) {
+    for line in lines {
+        let Some((key, value)) = line.trim().split_once(':') else {
+            continue;
+        };
+        let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+        match key.trim() {
+            "message" if result.status != TestStatus::Passed && !value.is_empty() => {
+                result.failure_message = Some(value.to_string());
+            }
+            "duration_ms" => {
+                if let Ok(duration_ms) = value.parse::<f64>() {
+                    result.duration_ms = duration_ms.round() as i64;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parses a TAP (version 12 to 14) stream. Only top-level test points are
+/// recorded; indented subtest output is skipped in favour of the summary line
+/// the producer writes for each subtest.
+pub(super) async fn parse<R>(reader: R, suite_name: &str) -> Result<Vec<NewTestResult>, Error>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut lines = reader.lines();
+    let mut collector = ResultCollector::default();
+    let mut pending: Option<NewTestResult> = None;
+    let mut diagnostics: Option<Vec<String>> = None;
+    let mut number = 0;
+
+    while let Some(line) = lines.next_line().await.map_err(invalid)? {
+        if let Some(block) = diagnostics.as_mut() {
+            if line.trim() == "..." {
+                if let Some(result) = pending.as_mut() {
+                    apply_diagnostics(result, block);
+                }
+                diagnostics = None;
+            } else {
+                block.push(line);
+            }
+            continue;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            if line.trim() == "---" && pending.is_some() {
+                diagnostics = Some(Vec::new());
+            }
+            continue;
+        }
+
+        if let Some(reason) = line.strip_prefix("Bail out!") {
+            return Err(invalid(format!("producer bailed out: {}", reason.trim())));
+        }
+
+        if let Some(result) = parse_test_point(&line, number + 1, suite_name) {
+            number += 1;
+            if let Some(previous) = pending.replace(result) {
+                collector.push(previous);
+            }
+        }
+    }
+
+    if diagnostics.is_some() {
+        return Err(invalid("unterminated YAML diagnostic block"));
+    }
+    if let Some(result) = pending {
+        collector.push(result);
+    }
+
+    Ok(collector.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn parses_tap_stream() {
+        let tap = "TAP version 13
+1..5
+ok 1 - creates a run
+not ok 2 - lists runs
+  ---
+  message: 'expected 200, got 500'
+  duration_ms: 12.4
+  ...
+ok 3 - exports # SKIP no CSV support yet
+not ok 4 # TODO flaky on CI
+# Subtest: nested
+    ok 1 - inner
+ok 5 - nested
+";
+
+        let results = parse(tap.as_bytes(), "tap").await.unwrap();
+        let summary: Vec<_> = results
+            .iter()
+            .map(|r| {
+                (
+                    r.case_name.as_str(),
+                    r.status,
+                    r.failure_message.as_deref(),
+                    r.duration_ms,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                ("creates a run", TestStatus::Passed, None, 0),
+                (
+                    "lists runs",
+                    TestStatus::Failed,
+                    Some("expected 200, got 500"),
+                    12
+                ),
+                (
+                    "exports",
+                    TestStatus::Skipped,
+                    Some("no CSV support yet"),
+                    0
+                ),
+                ("test 4", TestStatus::Skipped, Some("flaky on CI"), 0),
+                ("nested", TestStatus::Passed, None, 0),
+            ]
+        );
+        assert!(results.iter().all(|r| r.suite_name == "tap"));
+    }
+
+    #[tokio::test]
+    async fn rejects_bail_out() {
+        let tap = "1..2\nok 1\nBail out! database unavailable\n";
+        assert!(matches!(
+            parse(tap.as_bytes(), "tap").await,
+            Err(Error::InvalidReport(_))
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+use super::TestRunRepository;
+use crate::endpoints::test_run::TestRunQueryParams;
+use crate::error::{Error, BUILD_NUMBER_TAKEN};
+use crate::schema::test_run::{NewTestRun, TestRun, TestRunPage, TestRunPatch};
+use async_trait::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use tracing::info;
+use uuid::Uuid;
+
+/// Keeps test runs in process memory; everything is lost on restart. Meant for
+/// tests and demos.
+#[derive(Debug, Default)]
+pub struct MemoryTestRunRepository {
+    test_runs: RwLock<HashMap<Uuid, TestRun>>,
+}
+
+impl MemoryTestRunRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Postgres and SQLite both keep microseconds, so timestamps are truncated to
+/// match what the other backends would return.
+fn timestamp(timestamp: DateTime<Utc>) -> DateTime<Utc> {
+    timestamp.trunc_subsecs(6)
+}
+
+fn ensure_unique_build_number(
+    test_runs: &HashMap<Uuid, TestRun>,
+    build_number: &str,
+    except: Option<Uuid>,
+) -> Result<(), Error> {
+    let taken = test_runs
+        .values()
+        .any(|run| run.build_number == build_number && Some(run.test_run_id) != except);
+    if taken {
+        return Err(Error::Conflict(BUILD_NUMBER_TAKEN.to_string()));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl TestRunRepository for MemoryTestRunRepository {
+    async fn list(&self, query_params: &TestRunQueryParams) -> Result<TestRunPage, Error> {
+        query_params.validate()?;
+
+        info!("Querying memory for test runs");
+        let test_runs = self.test_runs.read().unwrap();
+        let mut matching: Vec<&TestRun> = test_runs
+            .values()
+            .filter(|run| {
+                (query_params.since..=query_params.until).contains(&run.build_timestamp)
+                    && query_params
+                        .test_run_id
+                        .is_none_or(|id| id == run.test_run_id)
+                    && query_params
+                        .build_number
+                        .as_ref()
+                        .is_none_or(|build_number| *build_number == run.build_number)
+            })
+            .collect();
+        matching.sort_by_key(|run| Reverse((run.build_timestamp, run.test_run_id)));
+
+        let per_page = query_params.per_page() as usize;
+        let offset = (query_params.page_num() as usize - 1).saturating_mul(per_page);
+
+        Ok(TestRunPage {
+            total: matching.len() as i64,
+            test_runs: matching
+                .into_iter()
+                .skip(offset)
+                .take(per_page)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    async fn get(&self, test_run_id: Uuid) -> Result<TestRun, Error> {
+        info!(%test_run_id, "Querying memory for test run");
+        self.test_runs
+            .read()
+            .unwrap()
+            .get(&test_run_id)
+            .cloned()
+            .ok_or_else(|| sqlx::Error::RowNotFound.into())
+    }
+
+    async fn create(&self, new_test_run: NewTestRun) -> Result<TestRun, Error> {
+        new_test_run.validate()?;
+
+        info!(build_number = %new_test_run.build_number, "Inserting test run");
+        let mut test_runs = self.test_runs.write().unwrap();
+        ensure_unique_build_number(&test_runs, &new_test_run.build_number, None)?;
+
+        let test_run = TestRun {
+            test_run_id: Uuid::new_v4(),
+            build_number: new_test_run.build_number,
+            build_url: new_test_run.build_url,
+            build_timestamp: timestamp(new_test_run.build_timestamp.unwrap_or_else(Utc::now)),
+        };
+        test_runs.insert(test_run.test_run_id, test_run.clone());
+
+        Ok(test_run)
+    }
+
+    async fn update(&self, test_run_id: Uuid, patch: TestRunPatch) -> Result<TestRun, Error> {
+        patch.validate()?;
+
+        info!(%test_run_id, "Updating test run");
+        let mut test_runs = self.test_runs.write().unwrap();
+        if let Some(build_number) = &patch.build_number {
+            ensure_unique_build_number(&test_runs, build_number, Some(test_run_id))?;
+        }
+
+        let test_run = test_runs
+            .get_mut(&test_run_id)
+            .ok_or(sqlx::Error::RowNotFound)?;
+        if let Some(build_number) = patch.build_number {
+            test_run.build_number = build_number;
+        }
+        if let Some(build_url) = patch.build_url {
+            test_run.build_url = build_url;
+        }
+        if let Some(build_timestamp) = patch.build_timestamp {
+            test_run.build_timestamp = timestamp(build_timestamp);
+        }
+
+        Ok(test_run.clone())
+    }
+
+    async fn delete(&self, test_run_id: Uuid) -> Result<(), Error> {
+        info!(%test_run_id, "Deleting test run");
+        self.test_runs
+            .write()
+            .unwrap()
+            .remove(&test_run_id)
+            .map(|_| ())
+            .ok_or_else(|| sqlx::Error::RowNotFound.into())
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap()
+    }
+
+    fn query_params() -> TestRunQueryParams {
+        TestRunQueryParams {
+            test_run_id: None,
+            build_number: None,
+            page_num: None,
+            per_page: None,
+            since: at(1),
+            until: at(31),
+        }
+    }
+
+    async fn create(repository: &MemoryTestRunRepository, build_number: &str, day: u32) -> TestRun {
+        repository
+            .create(NewTestRun {
+                build_number: build_number.to_string(),
+                build_url: None,
+                build_timestamp: Some(at(day)),
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn filters_and_pages_newest_first() {
+        let repository = MemoryTestRunRepository::new();
+        for (build_number, day) in [("1", 1), ("2", 2), ("3", 3), ("4", 4), ("5", 5)] {
+            create(&repository, build_number, day).await;
+        }
+
+        let page = repository
+            .list(&TestRunQueryParams {
+                page_num: Some(2),
+                per_page: Some(2),
+                since: at(2),
+                ..query_params()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.total, 4);
+        let build_numbers: Vec<_> = page.test_runs.iter().map(|r| &r.build_number).collect();
+        assert_eq!(build_numbers, ["3", "2"]);
+
+        let page = repository
+            .list(&TestRunQueryParams {
+                build_number: Some("5".to_string()),
+                ..query_params()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+
+        let page = repository
+            .list(&TestRunQueryParams {
+                page_num: Some(9),
+                ..query_params()
+            })
+            .await
+            .unwrap();
+        assert_eq!((page.total, page.test_runs.len()), (5, 0));
+
+        assert!(matches!(
+            repository
+                .list(&TestRunQueryParams {
+                    per_page: Some(0),
+                    ..query_params()
+                })
+                .await,
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn enforces_unique_build_numbers() {
+        let repository = MemoryTestRunRepository::new();
+        create(&repository, "1", 1).await;
+        let second = create(&repository, "2", 2).await;
+
+        let duplicate = NewTestRun {
+            build_number: "1".to_string(),
+            build_url: None,
+            build_timestamp: None,
+        };
+        assert!(matches!(
+            repository.create(duplicate).await,
+            Err(Error::Conflict(_))
+        ));
+
+        let rename = TestRunPatch {
+            build_number: Some("1".to_string()),
+            ..TestRunPatch::default()
+        };
+        assert!(matches!(
+            repository.update(second.test_run_id, rename).await,
+            Err(Error::Conflict(_))
+        ));
+
+        let unchanged = TestRunPatch {
+            build_number: Some("2".to_string()),
+            ..TestRunPatch::default()
+        };
+        repository
+            .update(second.test_run_id, unchanged)
+            .await
+            .unwrap();
+    }
+}
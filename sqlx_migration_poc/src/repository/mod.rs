@@ -0,0 +1,91 @@
+//! Storage of test runs behind a common interface, so that the test run
+//! endpoints behave the same on every backend.
+
+use crate::config::{Config, StorageBackend};
+use crate::db::{self, PgPools};
+use crate::endpoints::test_run::TestRunQueryParams;
+use crate::error::Error;
+use crate::schema::test_run::{NewTestRun, TestRun, TestRunPage, TestRunPatch};
+use async_trait::async_trait;
+use sqlx::{PgPool, SqlitePool};
+use std::sync::Arc;
+use tracing::info;
+use uuid::Uuid;
+
+mod memory;
+mod postgres;
+mod sqlite;
+
+pub use memory::MemoryTestRunRepository;
+pub use postgres::PgTestRunRepository;
+pub use sqlite::SqliteTestRunRepository;
+
+#[async_trait]
+pub trait TestRunRepository: Send + Sync {
+    /// One page of the runs matching `query_params`, newest first, along with
+    /// the number of matching runs across all pages.
+    async fn list(&self, query_params: &TestRunQueryParams) -> Result<TestRunPage, Error>;
+
+    async fn get(&self, test_run_id: Uuid) -> Result<TestRun, Error>;
+
+    async fn create(&self, new_test_run: NewTestRun) -> Result<TestRun, Error>;
+
+    async fn update(&self, test_run_id: Uuid, patch: TestRunPatch) -> Result<TestRun, Error>;
+
+    async fn delete(&self, test_run_id: Uuid) -> Result<(), Error>;
+
+    /// Checks that the backend can serve queries.
+    async fn ping(&self) -> Result<(), Error>;
+}
+
+/// The storage backend the server runs on.
+#[derive(Clone)]
+pub enum Storage {
+    Postgres(&'static PgPools),
+    Sqlite(SqlitePool),
+    Memory(Arc<MemoryTestRunRepository>),
+}
+
+impl Storage {
+    pub async fn connect(config: &Config) -> Result<Self, Error> {
+        info!(backend = ?config.storage.backend, "Connecting to storage");
+        match config.storage.backend {
+            StorageBackend::Postgres => {
+                Ok(Storage::Postgres(db::get_pools(&config.database).await?))
+            }
+            StorageBackend::Sqlite => Ok(Storage::Sqlite(
+                db::init_sqlite_pool(&config.storage.sqlite_path, &config.database).await?,
+            )),
+            StorageBackend::Memory => Ok(Storage::Memory(Arc::default())),
+        }
+    }
+
+    pub fn test_runs(&self) -> Arc<dyn TestRunRepository> {
+        match self {
+            Storage::Postgres(pools) => Arc::new(PgTestRunRepository::new(pools)),
+            Storage::Sqlite(pool) => Arc::new(SqliteTestRunRepository::new(pool.clone())),
+            Storage::Memory(repository) => repository.clone(),
+        }
+    }
+
+    /// The primary Postgres pool, for the features only Postgres supports.
+    pub fn pg_pool(&self) -> Option<&'static PgPool> {
+        self.pg_pools().map(PgPools::primary)
+    }
+
+    /// The Postgres pools, for routing read-only queries to a replica.
+    pub fn pg_pools(&self) -> Option<&'static PgPools> {
+        match self {
+            Storage::Postgres(pools) => Some(pools),
+            Storage::Sqlite(_) | Storage::Memory(_) => None,
+        }
+    }
+
+    pub async fn close(&self) {
+        match self {
+            Storage::Postgres(pools) => pools.close().await,
+            Storage::Sqlite(pool) => pool.close().await,
+            Storage::Memory(_) => {}
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use super::TestRunRepository;
+use crate::db::PgPools;
+use crate::endpoints::test_run::TestRunQueryParams;
+use crate::error::Error;
+use crate::schema::test_run::{NewTestRun, TestRun, TestRunPage, TestRunPatch};
+use async_trait::async_trait;
+use uuid::Uuid;
+
+/// Reads from the replica, when there is a healthy one, and writes to the
+/// primary.
+pub struct PgTestRunRepository {
+    pools: &'static PgPools,
+}
+
+impl PgTestRunRepository {
+    pub fn new(pools: &'static PgPools) -> Self {
+        Self { pools }
+    }
+}
+
+#[async_trait]
+impl TestRunRepository for PgTestRunRepository {
+    async fn list(&self, query_params: &TestRunQueryParams) -> Result<TestRunPage, Error> {
+        self.pools
+            .read(|mut conn| async move { TestRun::get_by_query_params(query_params, &mut conn).await })
+            .await
+    }
+
+    async fn get(&self, test_run_id: Uuid) -> Result<TestRun, Error> {
+        self.pools
+            .read(|mut conn| async move { TestRun::get_by_id(test_run_id, &mut conn).await })
+            .await
+    }
+
+    async fn create(&self, new_test_run: NewTestRun) -> Result<TestRun, Error> {
+        TestRun::create(new_test_run, &mut *self.pools.acquire().await?).await
+    }
+
+    async fn update(&self, test_run_id: Uuid, patch: TestRunPatch) -> Result<TestRun, Error> {
+        TestRun::update(test_run_id, patch, &mut *self.pools.acquire().await?).await
+    }
+
+    async fn delete(&self, test_run_id: Uuid) -> Result<(), Error> {
+        TestRun::delete(test_run_id, &mut *self.pools.acquire().await?).await
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        sqlx::query("select 1")
+            .execute(self.pools.primary())
+            .await?;
+        Ok(())
+    }
+}
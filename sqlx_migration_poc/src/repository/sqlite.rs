@@ -0,0 +1,169 @@
+use super::TestRunRepository;
+use crate::endpoints::test_run::TestRunQueryParams;
+use crate::error::Error;
+use crate::schema::test_run::{NewTestRun, TestRun, TestRunPage, TestRunPatch};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+pub struct SqliteTestRunRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteTestRunRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// SQLite has no timestamp type, so timestamps are stored as text with a
+/// fixed precision, which keeps them ordered when compared as strings.
+fn timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// The SQLite counterpart of the Postgres `filtered_query` in
+/// `schema::test_run`.
+fn filtered_query(
+    select: &str,
+    query_params: &TestRunQueryParams,
+) -> QueryBuilder<'static, Sqlite> {
+    let mut query = QueryBuilder::new(select);
+    query
+        .push(" where build_timestamp between ")
+        .push_bind(timestamp(query_params.since))
+        .push(" and ")
+        .push_bind(timestamp(query_params.until));
+
+    if let Some(id) = query_params.test_run_id {
+        query.push(" and test_run_id = ").push_bind(id);
+    }
+
+    if let Some(build_number) = &query_params.build_number {
+        query
+            .push(" and build_number = ")
+            .push_bind(build_number.clone());
+    }
+
+    query
+}
+
+// `insert ... returning` is avoided: sqlx can hand the connection back to the
+// pool before SQLite has finished the statement, hiding the write from other
+// connections for a moment.
+async fn select_by_id(test_run_id: Uuid, conn: &mut SqliteConnection) -> sqlx::Result<TestRun> {
+    sqlx::query_as("select * from test_run where test_run_id = ?")
+        .bind(test_run_id)
+        .fetch_one(conn)
+        .await
+}
+
+#[async_trait]
+impl TestRunRepository for SqliteTestRunRepository {
+    async fn list(&self, query_params: &TestRunQueryParams) -> Result<TestRunPage, Error> {
+        query_params.validate()?;
+
+        let per_page = i64::from(query_params.per_page());
+        let offset = i64::from(query_params.page_num() - 1) * per_page;
+
+        let mut count_query = filtered_query("select count(*) from test_run", query_params);
+        let mut page_query = filtered_query("select * from test_run", query_params);
+        page_query
+            .push(" order by build_timestamp desc, test_run_id desc limit ")
+            .push_bind(per_page)
+            .push(" offset ")
+            .push_bind(offset);
+
+        info!("Querying SQLite for test runs");
+        debug!(
+            "using SQL commands: {}; {}",
+            count_query.sql(),
+            page_query.sql()
+        );
+        let total = count_query
+            .build_query_scalar()
+            .fetch_one(&self.pool)
+            .await?;
+        let test_runs = page_query.build_query_as().fetch_all(&self.pool).await?;
+
+        Ok(TestRunPage { test_runs, total })
+    }
+
+    async fn get(&self, test_run_id: Uuid) -> Result<TestRun, Error> {
+        info!(%test_run_id, "Querying SQLite for test run");
+        Ok(select_by_id(test_run_id, &mut *self.pool.acquire().await?).await?)
+    }
+
+    async fn create(&self, new_test_run: NewTestRun) -> Result<TestRun, Error> {
+        new_test_run.validate()?;
+
+        let test_run_id = Uuid::new_v4();
+        info!(build_number = %new_test_run.build_number, "Inserting test run");
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "insert into test_run (test_run_id, build_number, build_url, build_timestamp) \
+             values (?, ?, ?, ?)",
+        )
+        .bind(test_run_id)
+        .bind(new_test_run.build_number)
+        .bind(new_test_run.build_url)
+        .bind(timestamp(
+            new_test_run.build_timestamp.unwrap_or_else(Utc::now),
+        ))
+        .execute(&mut *tx)
+        .await?;
+        let test_run = select_by_id(test_run_id, &mut tx).await?;
+        tx.commit().await?;
+
+        Ok(test_run)
+    }
+
+    async fn update(&self, test_run_id: Uuid, patch: TestRunPatch) -> Result<TestRun, Error> {
+        patch.validate()?;
+
+        info!(%test_run_id, "Updating test run");
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            "update test_run set \
+             build_number = coalesce(?1, build_number), \
+             build_url = case when ?2 then ?3 else build_url end, \
+             build_timestamp = coalesce(?4, build_timestamp) \
+             where test_run_id = ?5",
+        )
+        .bind(patch.build_number)
+        .bind(patch.build_url.is_some())
+        .bind(patch.build_url.flatten())
+        .bind(patch.build_timestamp.map(timestamp))
+        .bind(test_run_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+        let test_run = select_by_id(test_run_id, &mut tx).await?;
+        tx.commit().await?;
+
+        Ok(test_run)
+    }
+
+    async fn delete(&self, test_run_id: Uuid) -> Result<(), Error> {
+        info!(%test_run_id, "Deleting test run");
+        let result = sqlx::query("delete from test_run where test_run_id = ?")
+            .bind(test_run_id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+        Ok(())
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        sqlx::query("select 1").execute(&self.pool).await?;
+        Ok(())
+    }
+}
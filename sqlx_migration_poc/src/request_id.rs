@@ -0,0 +1,46 @@
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use uuid::Uuid;
+
+pub(crate) static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The id of the request currently being handled, if any.
+pub(crate) fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+fn is_valid(id: &HeaderValue) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.as_bytes().iter().all(u8::is_ascii_graphic)
+}
+
+/// Middleware that reuses the caller's `x-request-id` (or assigns a fresh one),
+/// makes it available to handlers through [`current`] and echoes it back on the
+/// response.
+pub(crate) async fn propagate(mut request: Request, next: Next) -> Response {
+    let id = match request.headers().get(&X_REQUEST_ID) {
+        Some(id) if is_valid(id) => id.clone(),
+        _ => {
+            let id = HeaderValue::try_from(Uuid::new_v4().to_string())
+                .expect("a UUID is a valid header value");
+            request
+                .headers_mut()
+                .insert(X_REQUEST_ID.clone(), id.clone());
+            id
+        }
+    };
+    let id_string = id.to_str().unwrap_or_default().to_string();
+
+    let mut response = REQUEST_ID.scope(id_string, next.run(request)).await;
+    response.headers_mut().insert(X_REQUEST_ID.clone(), id);
+    response
+}
@@ -0,0 +1,96 @@
+//! Upkeep of the monthly `test_run` partitions: the partitions for this month
+//! and the next are created ahead of time, and the runs that have outlived
+//! the configured retention are dropped or archived, along with their results
+//! and events. Instances take turns through a Postgres advisory lock.
+
+use crate::config::RetentionConfig;
+use crate::error::Error;
+use crate::schema::test_run_partition::TestRunPartition;
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use sqlx::{Connection, PgConnection, PgPool};
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+/// Key of the advisory lock held while enforcing retention, so that only one
+/// instance does so at a time.
+pub const LOCK_ID: i64 = 0x7265_7465_6e74_696f;
+
+/// Enforces `config` every `config.interval()`, starting right away. Runs
+/// until it is aborted.
+pub(crate) async fn run(pool: &'static PgPool, config: RetentionConfig) {
+    let mut interval = time::interval(config.interval());
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(e) = enforce(pool, &config).await {
+            warn!("Failed to enforce test run retention: {e}");
+        }
+    }
+}
+
+/// Creates the partitions for this month and the next if missing, then
+/// removes the test runs older than `config.max_age()`, if set. Does nothing
+/// while another instance holds the lock.
+pub async fn enforce(pool: &PgPool, config: &RetentionConfig) -> Result<(), Error> {
+    // A dedicated connection, so that closing it always releases the lock,
+    // even when the task is aborted halfway.
+    let mut conn = PgConnection::connect_with(&pool.connect_options()).await?;
+    let locked: bool = sqlx::query_scalar("select pg_try_advisory_lock($1)")
+        .bind(LOCK_ID)
+        .fetch_one(&mut conn)
+        .await?;
+    let result = if locked {
+        enforce_locked(pool, config).await
+    } else {
+        debug!("Another instance is enforcing test run retention");
+        Ok(())
+    };
+    conn.close().await?;
+
+    result
+}
+
+async fn enforce_locked(pool: &PgPool, config: &RetentionConfig) -> Result<(), Error> {
+    let now = Utc::now();
+    let partitions = TestRunPartition::get_all(pool).await?;
+
+    let this_month = NaiveDate::from_ymd_opt(now.year(), now.month(), 1)
+        .expect("the first of the month is a valid date");
+    for month in [this_month, this_month + Months::new(1)] {
+        if !partitions.iter().any(|partition| partition.month == month) {
+            let partition = TestRunPartition::create(month, pool).await?;
+            info!(partition = partition.name, "Created test run partition");
+        }
+    }
+
+    let Some(max_age) = config.max_age() else {
+        return Ok(());
+    };
+    let cutoff = now - max_age;
+    for partition in partitions.iter().filter(|p| p.end() <= cutoff) {
+        let removed = partition.remove(config.action, pool).await?;
+        info!(
+            partition = partition.name,
+            month = %partition.month.format("%Y-%m"),
+            action = ?config.action,
+            test_runs = removed.test_runs,
+            test_results = removed.test_results,
+            test_run_events = removed.test_run_events,
+            "Removed expired test run partition"
+        );
+    }
+
+    let removed = TestRunPartition::remove_default_before(cutoff, config.action, pool).await?;
+    if removed.test_runs > 0 {
+        info!(
+            partition = "test_run_default",
+            %cutoff,
+            action = ?config.action,
+            test_runs = removed.test_runs,
+            test_results = removed.test_results,
+            test_run_events = removed.test_run_events,
+            "Removed expired test runs"
+        );
+    }
+    Ok(())
+}
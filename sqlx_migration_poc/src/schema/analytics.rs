@@ -0,0 +1,171 @@
+//! Aggregates over test results within a `since`/`until` window. All of the
+//! heavy lifting happens in SQL; only the aggregated rows leave the database.
+//! Skipped results are ignored throughout.
+
+use crate::endpoints::analytics::AnalyticsQueryParams;
+use crate::error::Error;
+use async_graphql::SimpleObject;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+use tracing::{debug, info};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct FailureRate {
+    pub(crate) suite_name: String,
+    pub(crate) case_name: String,
+    pub(crate) runs: i64,
+    pub(crate) failures: i64,
+    pub(crate) failure_rate: f64,
+}
+
+/// A test whose outcome keeps changing between consecutive builds, or that
+/// only passed after being retried.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct FlakyTest {
+    pub(crate) suite_name: String,
+    pub(crate) case_name: String,
+    pub(crate) runs: i64,
+    pub(crate) failures: i64,
+    pub(crate) flips: i64,
+    pub(crate) flip_rate: f64,
+    pub(crate) passed_after_retry: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow, SimpleObject)]
+#[serde(rename_all = "camelCase")]
+pub struct SlowTest {
+    pub(crate) suite_name: String,
+    pub(crate) case_name: String,
+    pub(crate) runs: i64,
+    pub(crate) p50_ms: f64,
+    pub(crate) p95_ms: f64,
+    pub(crate) max_ms: i64,
+}
+
+/// Pushes the joins from a result (aliased `r`) to its run, case and suite,
+/// and the filters shared by every aggregate.
+fn push_window(query: &mut QueryBuilder<'static, Postgres>, query_params: &AnalyticsQueryParams) {
+    query
+        .push(
+            " from test_result r \
+             join test_run run on run.test_run_id = r.test_run_id \
+             join test_case tc on tc.test_case_id = r.test_case_id \
+             join test_suite ts on ts.test_suite_id = tc.test_suite_id \
+             where r.status <> 'skipped' and run.build_timestamp between ",
+        )
+        .push_bind(query_params.since)
+        .push(" and ")
+        .push_bind(query_params.until);
+
+    if let Some(suite_name) = &query_params.suite_name {
+        query.push(" and ts.name = ").push_bind(suite_name.clone());
+    }
+}
+
+fn push_limit(query: &mut QueryBuilder<'static, Postgres>, query_params: &AnalyticsQueryParams) {
+    query
+        .push(" limit ")
+        .push_bind(i64::from(query_params.limit()));
+}
+
+impl FailureRate {
+    pub(crate) async fn get(
+        query_params: &AnalyticsQueryParams,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<FailureRate>, Error> {
+        query_params.validate()?;
+
+        let mut query = QueryBuilder::new(
+            "select ts.name as suite_name, tc.name as case_name, \
+             count(*) as runs, \
+             count(*) filter (where r.status in ('failed', 'error')) as failures, \
+             (count(*) filter (where r.status in ('failed', 'error')))::float8 / count(*) \
+             as failure_rate",
+        );
+        push_window(&mut query, query_params);
+        query
+            .push(" group by ts.name, tc.name having count(*) >= ")
+            .push_bind(i64::from(query_params.min_runs()))
+            .push(" order by failure_rate desc, failures desc, suite_name, case_name");
+        push_limit(&mut query, query_params);
+
+        info!("Querying DB for failure rates");
+        debug!("using SQL command: {}", query.sql());
+        Ok(query.build_query_as().fetch_all(conn).await?)
+    }
+}
+
+impl FlakyTest {
+    pub(crate) async fn get(
+        query_params: &AnalyticsQueryParams,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<FlakyTest>, Error> {
+        query_params.validate()?;
+
+        let mut query = QueryBuilder::new(
+            "with outcomes as (\
+             select ts.name as suite_name, tc.name as case_name, \
+             r.status in ('failed', 'error') as failed, \
+             r.status = 'passed' and r.retries > 0 as passed_after_retry, \
+             lag(r.status in ('failed', 'error')) over (\
+             partition by r.test_case_id \
+             order by run.build_timestamp, run.test_run_id) as previously_failed",
+        );
+        push_window(&mut query, query_params);
+        query
+            .push(
+                ") select suite_name, case_name, \
+                 count(*) as runs, \
+                 count(*) filter (where failed) as failures, \
+                 count(*) filter (where failed <> previously_failed) as flips, \
+                 coalesce(\
+                 (count(*) filter (where failed <> previously_failed))::float8 \
+                 / nullif(count(*) - 1, 0), 0) as flip_rate, \
+                 count(*) filter (where passed_after_retry) as passed_after_retry \
+                 from outcomes \
+                 group by suite_name, case_name \
+                 having count(*) >= ",
+            )
+            .push_bind(i64::from(query_params.min_runs()))
+            .push(
+                " and (count(*) filter (where failed <> previously_failed) > 0 \
+                 or count(*) filter (where passed_after_retry) > 0) \
+                 order by flip_rate desc, flips desc, passed_after_retry desc, \
+                 suite_name, case_name",
+            );
+        push_limit(&mut query, query_params);
+
+        info!("Querying DB for flaky tests");
+        debug!("using SQL command: {}", query.sql());
+        Ok(query.build_query_as().fetch_all(conn).await?)
+    }
+}
+
+impl SlowTest {
+    pub(crate) async fn get(
+        query_params: &AnalyticsQueryParams,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<SlowTest>, Error> {
+        query_params.validate()?;
+
+        let mut query = QueryBuilder::new(
+            "select ts.name as suite_name, tc.name as case_name, \
+             count(*) as runs, \
+             percentile_cont(0.5) within group (order by r.duration_ms) as p50_ms, \
+             percentile_cont(0.95) within group (order by r.duration_ms) as p95_ms, \
+             max(r.duration_ms) as max_ms",
+        );
+        push_window(&mut query, query_params);
+        query
+            .push(" group by ts.name, tc.name having count(*) >= ")
+            .push_bind(i64::from(query_params.min_runs()))
+            .push(" order by p95_ms desc, p50_ms desc, suite_name, case_name");
+        push_limit(&mut query, query_params);
+
+        info!("Querying DB for slowest tests");
+        debug!("using SQL command: {}", query.sql());
+        Ok(query.build_query_as().fetch_all(conn).await?)
+    }
+}
@@ -0,0 +1,229 @@
+use crate::error::{Error, FieldError};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
+use sqlx::PgPool;
+use std::fmt;
+use tracing::info;
+use uuid::Uuid;
+
+/// Prefix of every issued token, so that leaked tokens are easy to recognise.
+const TOKEN_PREFIX: &str = "poc_";
+
+const MAX_NAME_LEN: usize = 255;
+
+/// How stale `last_used_at` may get before a request using the token updates it.
+const LAST_USED_RESOLUTION: Duration = Duration::minutes(1);
+
+/// What a token may be used for.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type, clap::ValueEnum,
+)]
+#[sqlx(type_name = "text")]
+pub enum Scope {
+    /// Read test runs, results, exports and analytics
+    #[serde(rename = "runs:read")]
+    #[sqlx(rename = "runs:read")]
+    #[clap(name = "runs:read")]
+    RunsRead,
+    /// Create, change and delete test runs and their results
+    #[serde(rename = "runs:write")]
+    #[sqlx(rename = "runs:write")]
+    #[clap(name = "runs:write")]
+    RunsWrite,
+    /// Manage API tokens and inspect the schema
+    #[serde(rename = "admin")]
+    #[sqlx(rename = "admin")]
+    #[clap(name = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Scope::RunsRead => "runs:read",
+            Scope::RunsWrite => "runs:write",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PgHasArrayType for Scope {
+    fn array_type_info() -> PgTypeInfo {
+        <String as PgHasArrayType>::array_type_info()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    pub(crate) api_token_id: Uuid,
+    pub(crate) name: String,
+    pub(crate) scopes: Vec<Scope>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) last_used_at: Option<DateTime<Utc>>,
+    pub(crate) revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewApiToken {
+    pub(crate) name: String,
+    pub(crate) scopes: Vec<Scope>,
+}
+
+/// A freshly issued token. This is the only time the token itself is known.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuedApiToken {
+    #[serde(flatten)]
+    pub(crate) api_token: ApiToken,
+    pub(crate) token: String,
+}
+
+impl NewApiToken {
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        if self.name.trim().is_empty() {
+            errors.push(FieldError::new("name", "must not be blank"));
+        } else if self.name.chars().count() > MAX_NAME_LEN {
+            errors.push(FieldError::new(
+                "name",
+                format!("must be at most {MAX_NAME_LEN} characters"),
+            ));
+        }
+        if self.scopes.is_empty() {
+            errors.push(FieldError::new("scopes", "must not be empty"));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(errors))
+        }
+    }
+}
+
+fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{TOKEN_PREFIX}{}", hex::encode(bytes))
+}
+
+impl ApiToken {
+    pub(crate) async fn issue(
+        new_api_token: NewApiToken,
+        pool: &PgPool,
+    ) -> Result<IssuedApiToken, Error> {
+        new_api_token.validate()?;
+
+        let mut scopes = new_api_token.scopes;
+        scopes.sort_by_key(Scope::as_str);
+        scopes.dedup();
+
+        info!(name = %new_api_token.name, ?scopes, "Issuing API token");
+        let token = generate_token();
+        let api_token = sqlx::query_as(
+            "insert into api_token (name, token_hash, scopes) values ($1, $2, $3) \
+             returning api_token_id, name, scopes, created_at, last_used_at, revoked_at",
+        )
+        .bind(new_api_token.name)
+        .bind(hash_token(&token))
+        .bind(scopes)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(IssuedApiToken { api_token, token })
+    }
+
+    pub(crate) async fn get_all(pool: &PgPool) -> Result<Vec<ApiToken>, Error> {
+        info!("Querying DB for API tokens");
+        Ok(sqlx::query_as(
+            "select api_token_id, name, scopes, created_at, last_used_at, revoked_at \
+             from api_token order by created_at, api_token_id",
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Revokes a token for good. Revoking a revoked token is not an error.
+    pub(crate) async fn revoke(api_token_id: Uuid, pool: &PgPool) -> Result<(), Error> {
+        info!(%api_token_id, "Revoking API token");
+        let result = sqlx::query(
+            "update api_token set revoked_at = coalesce(revoked_at, now()) \
+             where api_token_id = $1",
+        )
+        .bind(api_token_id)
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+        Ok(())
+    }
+
+    /// Looks up the live token matching `token`. Its `last_used_at` is only
+    /// brought up to date once it is [`LAST_USED_RESOLUTION`] old, so that
+    /// authenticating a request does not write to the database every time.
+    pub(crate) async fn authenticate(
+        token: &str,
+        pool: &PgPool,
+    ) -> Result<Option<ApiToken>, Error> {
+        let api_token: Option<ApiToken> = sqlx::query_as(
+            "select api_token_id, name, scopes, created_at, last_used_at, revoked_at \
+             from api_token where token_hash = $1 and revoked_at is null",
+        )
+        .bind(hash_token(token))
+        .fetch_optional(pool)
+        .await?;
+
+        let Some(mut api_token) = api_token else {
+            return Ok(None);
+        };
+        let now = Utc::now();
+        if api_token
+            .last_used_at
+            .is_none_or(|last_used_at| now - last_used_at >= LAST_USED_RESOLUTION)
+        {
+            // Other requests may have updated it since it was read.
+            sqlx::query(
+                "update api_token set last_used_at = $2 \
+                 where api_token_id = $1 and (last_used_at is null or last_used_at <= $3)",
+            )
+            .bind(api_token.api_token_id)
+            .bind(now)
+            .bind(now - LAST_USED_RESOLUTION)
+            .execute(pool)
+            .await?;
+            api_token.last_used_at = Some(now);
+        }
+        Ok(Some(api_token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_distinct_prefixed_tokens() {
+        let token = generate_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 64);
+        assert_ne!(token, generate_token());
+        assert_eq!(hash_token(&token).len(), 32);
+    }
+}
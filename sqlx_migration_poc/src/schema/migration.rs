@@ -0,0 +1,96 @@
+use crate::db::MIGRATOR;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use tracing::info;
+
+/// A row of sqlx's `_sqlx_migrations` bookkeeping table.
+#[derive(Debug, sqlx::FromRow)]
+struct AppliedMigration {
+    version: i64,
+    description: String,
+    installed_on: DateTime<Utc>,
+    success: bool,
+    checksum: Vec<u8>,
+}
+
+/// A migration that is embedded in the binary, applied to the database, or
+/// both.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationStatus {
+    pub(crate) version: i64,
+    pub(crate) description: String,
+    pub(crate) embedded: bool,
+    pub(crate) applied: bool,
+    pub(crate) installed_on: Option<DateTime<Utc>>,
+    /// `false` when the applied migration failed or no longer matches the
+    /// embedded one; `None` unless it is both embedded and applied.
+    pub(crate) checksum_matches: Option<bool>,
+}
+
+impl MigrationStatus {
+    /// Whether the database holds a migration that failed, was changed after
+    /// being applied, or is unknown to this binary.
+    pub(crate) fn is_drifted(&self) -> bool {
+        self.applied && self.checksum_matches != Some(true)
+    }
+
+    pub(crate) async fn get_all(pool: &PgPool) -> Result<Vec<MigrationStatus>, sqlx::Error> {
+        info!("Querying DB for applied migrations");
+        let table_exists: bool =
+            sqlx::query_scalar("select to_regclass('_sqlx_migrations') is not null")
+                .fetch_one(pool)
+                .await?;
+        let applied: Vec<AppliedMigration> = if table_exists {
+            sqlx::query_as(
+                "select version, description, installed_on, success, checksum \
+                 from _sqlx_migrations order by version",
+            )
+            .fetch_all(pool)
+            .await?
+        } else {
+            Vec::new()
+        };
+
+        let mut statuses = BTreeMap::new();
+        for migration in MIGRATOR
+            .iter()
+            .filter(|m| !m.migration_type.is_down_migration())
+        {
+            statuses.insert(
+                migration.version,
+                MigrationStatus {
+                    version: migration.version,
+                    description: migration.description.to_string(),
+                    embedded: true,
+                    applied: false,
+                    installed_on: None,
+                    checksum_matches: None,
+                },
+            );
+        }
+
+        for row in applied {
+            let embedded = MIGRATOR
+                .iter()
+                .find(|m| m.version == row.version && !m.migration_type.is_down_migration());
+            let status = statuses
+                .entry(row.version)
+                .or_insert_with(|| MigrationStatus {
+                    version: row.version,
+                    description: row.description.clone(),
+                    embedded: false,
+                    applied: false,
+                    installed_on: None,
+                    checksum_matches: None,
+                });
+            status.applied = true;
+            status.installed_on = Some(row.installed_on);
+            status.checksum_matches = embedded.map(|m| row.success && *m.checksum == *row.checksum);
+        }
+
+        Ok(statuses.into_values().collect())
+    }
+}
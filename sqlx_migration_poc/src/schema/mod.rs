@@ -1 +1,9 @@
+pub mod analytics;
+pub mod api_token;
+pub mod migration;
+pub mod test_case;
+pub mod test_result;
 pub mod test_run;
+pub mod test_run_event;
+pub mod test_run_partition;
+pub mod test_suite;
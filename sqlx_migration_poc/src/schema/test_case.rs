@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use tracing::debug;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCase {
+    pub(crate) test_case_id: Uuid,
+    pub(crate) test_suite_id: Uuid,
+    pub(crate) name: String,
+}
+
+impl TestCase {
+    /// Makes sure a case exists for every `(suite_names[i], case_names[i])`
+    /// pair. The suites themselves must already exist.
+    pub(crate) async fn ensure_exist(
+        suite_names: &[String],
+        case_names: &[String],
+        conn: &mut PgConnection,
+    ) -> Result<(), sqlx::Error> {
+        debug!(count = case_names.len(), "Upserting test cases");
+        sqlx::query(
+            "insert into test_case (test_suite_id, name) \
+             select distinct s.test_suite_id, c.case_name \
+             from unnest($1::text[], $2::text[]) as c(suite_name, case_name) \
+             join test_suite s on s.name = c.suite_name \
+             on conflict (test_suite_id, name) do nothing",
+        )
+        .bind(suite_names)
+        .bind(case_names)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use crate::error::{Error, FieldError};
+use crate::schema::test_case::TestCase;
+use crate::schema::test_suite::TestSuite;
+use async_graphql::{Enum, SimpleObject};
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, PgConnection, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use tracing::{debug, info};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+pub(crate) const MAX_BATCH_SIZE: usize = 10_000;
+
+/// Selects results (aliased `tr`) along with the names of their suite and case.
+const SELECT_RESULTS: &str = "select tr.test_result_id, tr.test_run_id, ts.name as suite_name, \
+     tc.name as case_name, tr.status, tr.duration_ms, tr.failure_message, tr.retries \
+     from test_result tr \
+     join test_case tc on tc.test_case_id = tr.test_case_id \
+     join test_suite ts on ts.test_suite_id = tc.test_suite_id";
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type, ToSchema, Enum,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+    Error,
+}
+
+impl TestStatus {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            TestStatus::Passed => "passed",
+            TestStatus::Failed => "failed",
+            TestStatus::Skipped => "skipped",
+            TestStatus::Error => "error",
+        }
+    }
+}
+
+/// The outcome of one test case within a test run.
+#[derive(
+    Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema, SimpleObject,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct TestResult {
+    pub(crate) test_result_id: Uuid,
+    pub(crate) test_run_id: Uuid,
+    pub(crate) suite_name: String,
+    pub(crate) case_name: String,
+    pub(crate) status: TestStatus,
+    pub(crate) duration_ms: i64,
+    pub(crate) failure_message: Option<String>,
+    pub(crate) retries: i32,
+}
+
+/// A test case outcome as submitted by CI. Suites and cases are created on
+/// first sight, so callers only ever deal in names.
+#[derive(Clone, Debug, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTestResult {
+    pub(crate) suite_name: String,
+    pub(crate) case_name: String,
+    pub(crate) status: TestStatus,
+    #[serde(default)]
+    pub(crate) duration_ms: i64,
+    pub(crate) failure_message: Option<String>,
+    #[serde(default)]
+    pub(crate) retries: i32,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TestResultQueryParams {
+    pub(crate) status: Option<TestStatus>,
+    pub(crate) suite_name: Option<String>,
+    pub(crate) case_name: Option<String>,
+}
+
+impl TestResultQueryParams {
+    /// Appends a condition for each filter that is set to `query`, which must
+    /// select from [`SELECT_RESULTS`] and already have a `where` clause.
+    fn push_filters(&self, query: &mut QueryBuilder<Postgres>) {
+        if let Some(status) = self.status {
+            query.push(" and tr.status = ").push_bind(status.as_str());
+        }
+        if let Some(suite_name) = &self.suite_name {
+            query.push(" and ts.name = ").push_bind(suite_name.clone());
+        }
+        if let Some(case_name) = &self.case_name {
+            query.push(" and tc.name = ").push_bind(case_name.clone());
+        }
+    }
+}
+
+fn validate_batch(results: &[NewTestResult]) -> Result<(), Error> {
+    let mut errors = Vec::new();
+    if results.len() > MAX_BATCH_SIZE {
+        errors.push(FieldError::new(
+            "results",
+            format!("must contain at most {MAX_BATCH_SIZE} entries"),
+        ));
+    }
+
+    let mut seen = HashMap::new();
+    for (i, result) in results.iter().enumerate() {
+        if result.suite_name.trim().is_empty() {
+            errors.push(FieldError::new(
+                format!("[{i}].suiteName"),
+                "must not be blank",
+            ));
+        }
+        if result.case_name.trim().is_empty() {
+            errors.push(FieldError::new(
+                format!("[{i}].caseName"),
+                "must not be blank",
+            ));
+        }
+        if result.duration_ms < 0 {
+            errors.push(FieldError::new(
+                format!("[{i}].durationMs"),
+                "must not be negative",
+            ));
+        }
+        if result.retries < 0 {
+            errors.push(FieldError::new(
+                format!("[{i}].retries"),
+                "must not be negative",
+            ));
+        }
+        if let Some(first) = seen.insert((&result.suite_name, &result.case_name), i) {
+            errors.push(FieldError::new(
+                format!("[{i}].caseName"),
+                format!("duplicates the case at [{first}]"),
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+impl TestResult {
+    /// Records `results` against a test run, replacing any earlier result for
+    /// the same case. Runs on the caller's connection so that it can take part
+    /// in a wider transaction.
+    pub(crate) async fn insert_batch(
+        test_run_id: Uuid,
+        results: &[NewTestResult],
+        conn: &mut PgConnection,
+    ) -> Result<u64, Error> {
+        validate_batch(results)?;
+
+        let suite_names: Vec<String> = results.iter().map(|r| r.suite_name.clone()).collect();
+        let case_names: Vec<String> = results.iter().map(|r| r.case_name.clone()).collect();
+        let statuses: Vec<&str> = results.iter().map(|r| r.status.as_str()).collect();
+        let durations: Vec<i64> = results.iter().map(|r| r.duration_ms).collect();
+        let failure_messages: Vec<Option<String>> =
+            results.iter().map(|r| r.failure_message.clone()).collect();
+        let retries: Vec<i32> = results.iter().map(|r| r.retries).collect();
+
+        TestSuite::ensure_exist(&suite_names, conn).await?;
+        TestCase::ensure_exist(&suite_names, &case_names, conn).await?;
+
+        info!(%test_run_id, count = results.len(), "Inserting test results");
+        let result = sqlx::query(
+            "insert into test_result \
+             (test_run_id, test_case_id, status, duration_ms, failure_message, retries) \
+             select $1, tc.test_case_id, r.status, r.duration_ms, r.failure_message, r.retries \
+             from unnest($2::text[], $3::text[], $4::text[], $5::int8[], $6::text[], $7::int4[]) \
+             as r(suite_name, case_name, status, duration_ms, failure_message, retries) \
+             join test_suite ts on ts.name = r.suite_name \
+             join test_case tc on tc.test_suite_id = ts.test_suite_id and tc.name = r.case_name \
+             on conflict (test_run_id, test_case_id) do update set \
+             status = excluded.status, \
+             duration_ms = excluded.duration_ms, \
+             failure_message = excluded.failure_message, \
+             retries = excluded.retries",
+        )
+        .bind(test_run_id)
+        .bind(&suite_names)
+        .bind(&case_names)
+        .bind(&statuses)
+        .bind(&durations)
+        .bind(&failure_messages)
+        .bind(&retries)
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Records `results` against an existing test run in a single transaction.
+    pub(crate) async fn submit(
+        test_run_id: Uuid,
+        results: &[NewTestResult],
+        conn: &mut PgConnection,
+    ) -> Result<u64, Error> {
+        let mut tx = conn.begin().await?;
+
+        sqlx::query("select 1 from test_run where test_run_id = $1 for key share")
+            .bind(test_run_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        let count = Self::insert_batch(test_run_id, results, &mut tx).await?;
+        tx.commit().await?;
+
+        Ok(count)
+    }
+
+    pub(crate) async fn get_by_test_run(
+        test_run_id: Uuid,
+        query_params: &TestResultQueryParams,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<TestResult>, Error> {
+        sqlx::query("select 1 from test_run where test_run_id = $1")
+            .bind(test_run_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+
+        let mut query: QueryBuilder<Postgres> = QueryBuilder::new(SELECT_RESULTS);
+        query
+            .push(" where tr.test_run_id = ")
+            .push_bind(test_run_id);
+        query_params.push_filters(&mut query);
+        query.push(" order by ts.name, tc.name");
+
+        info!(%test_run_id, "Querying DB for test results");
+        debug!("using SQL command: {}", query.sql());
+        Ok(query.build_query_as().fetch_all(conn).await?)
+    }
+
+    /// The results of each of `test_run_ids` matching `query_params` in one
+    /// query, for batch loading.
+    pub(crate) async fn get_by_test_runs(
+        test_run_ids: &[Uuid],
+        query_params: &TestResultQueryParams,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<TestResult>, Error> {
+        let mut query: QueryBuilder<Postgres> = QueryBuilder::new(SELECT_RESULTS);
+        query
+            .push(" where tr.test_run_id = any(")
+            .push_bind(test_run_ids)
+            .push(")");
+        query_params.push_filters(&mut query);
+        query.push(" order by tr.test_run_id, ts.name, tc.name");
+
+        info!(
+            count = test_run_ids.len(),
+            "Querying DB for test results of runs"
+        );
+        debug!("using SQL command: {}", query.sql());
+        Ok(query.build_query_as().fetch_all(conn).await?)
+    }
+}
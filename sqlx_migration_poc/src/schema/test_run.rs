@@ -1,13 +1,23 @@
 use crate::endpoints::test_run::TestRunQueryParams;
+use crate::error::{Error, FieldError};
+use crate::schema::test_result::{NewTestResult, TestResult, MAX_BATCH_SIZE};
+use async_graphql::SimpleObject;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use sql_builder::{quote, SqlBuilder};
-use sqlx::PgPool;
+use futures_util::stream::{BoxStream, StreamExt};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::{Connection, PgConnection, PgPool, Postgres, QueryBuilder};
 use tracing::{debug, info};
+use url::Url;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+const MAX_BUILD_NUMBER_LEN: usize = 255;
+
+#[derive(
+    Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema, SimpleObject,
+)]
 #[serde(rename_all = "camelCase")]
+#[graphql(complex)]
 pub struct TestRun {
     pub(crate) test_run_id: Uuid,
     pub(crate) build_number: String,
@@ -15,24 +25,398 @@ pub struct TestRun {
     pub(crate) build_timestamp: DateTime<Utc>,
 }
 
-impl TestRun {
-    pub(crate) async fn get_by_query_params(
-        query_params: TestRunQueryParams,
-        pool: &PgPool,
-    ) -> Result<Vec<TestRun>, sqlx::Error> {
-        let mut query = SqlBuilder::select_from("test_run");
-        query.field("*");
+/// One page of test runs along with the number of runs matching the filters
+/// across all pages.
+#[derive(Debug)]
+pub struct TestRunPage {
+    pub(crate) test_runs: Vec<TestRun>,
+    pub(crate) total: i64,
+}
+
+/// The position of a test run in the newest-first ordering, used as a keyset
+/// pagination cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TestRunCursor {
+    pub(crate) build_timestamp: DateTime<Utc>,
+    pub(crate) test_run_id: Uuid,
+}
 
-        if let Some(id) = query_params.test_run_id {
-            query.and_where_eq("test_run_id", quote(id));
+impl From<&TestRun> for TestRunCursor {
+    fn from(test_run: &TestRun) -> Self {
+        Self {
+            build_timestamp: test_run.build_timestamp,
+            test_run_id: test_run.test_run_id,
         }
+    }
+}
+
+/// Payload for recording a new test run. `build_timestamp` defaults to now.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTestRun {
+    pub(crate) build_number: String,
+    pub(crate) build_url: Option<String>,
+    pub(crate) build_timestamp: Option<DateTime<Utc>>,
+}
+
+/// Partial update of a test run. Absent fields are left untouched, while an
+/// explicit `"buildUrl": null` clears the build URL.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TestRunPatch {
+    pub(crate) build_number: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[schema(value_type = Option<String>, nullable)]
+    pub(crate) build_url: Option<Option<String>>,
+    pub(crate) build_timestamp: Option<DateTime<Utc>>,
+}
+
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+fn validate_build_number(build_number: &str, errors: &mut Vec<FieldError>) {
+    if build_number.trim().is_empty() {
+        errors.push(FieldError::new("buildNumber", "must not be blank"));
+    } else if build_number.chars().count() > MAX_BUILD_NUMBER_LEN {
+        errors.push(FieldError::new(
+            "buildNumber",
+            format!("must be at most {MAX_BUILD_NUMBER_LEN} characters"),
+        ));
+    } else if build_number.chars().any(char::is_control) {
+        errors.push(FieldError::new(
+            "buildNumber",
+            "must not contain control characters",
+        ));
+    }
+}
+
+fn validate_build_url(build_url: &str, errors: &mut Vec<FieldError>) {
+    match Url::parse(build_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {}
+        Ok(_) => errors.push(FieldError::new(
+            "buildUrl",
+            "must be an absolute http or https URL",
+        )),
+        Err(e) => errors.push(FieldError::new("buildUrl", format!("is not a URL: {e}"))),
+    }
+}
+
+fn into_result(errors: Vec<FieldError>) -> Result<(), Error> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+impl NewTestRun {
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        validate_build_number(&self.build_number, &mut errors);
+        if let Some(build_url) = &self.build_url {
+            validate_build_url(build_url, &mut errors);
+        }
+        into_result(errors)
+    }
+}
+
+impl TestRunPatch {
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let mut errors = Vec::new();
+        if let Some(build_number) = &self.build_number {
+            validate_build_number(build_number, &mut errors);
+        }
+        if let Some(Some(build_url)) = &self.build_url {
+            validate_build_url(build_url, &mut errors);
+        }
+        into_result(errors)
+    }
+}
+
+/// Starts a query over `test_run` restricted by the filters in `query_params`.
+/// Every filter value is sent as a bound parameter, so user input can never
+/// alter the shape of the SQL statement.
+fn filtered_query(
+    select: &str,
+    query_params: &TestRunQueryParams,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query = QueryBuilder::new(select);
+    query
+        .push(" where build_timestamp between ")
+        .push_bind(query_params.since)
+        .push(" and ")
+        .push_bind(query_params.until);
+
+    if let Some(id) = query_params.test_run_id {
+        query.push(" and test_run_id = ").push_bind(id);
+    }
+
+    if let Some(build_number) = &query_params.build_number {
+        query
+            .push(" and build_number = ")
+            .push_bind(build_number.clone());
+    }
+
+    query
+}
+
+fn count_query(query_params: &TestRunQueryParams) -> QueryBuilder<'static, Postgres> {
+    filtered_query("select count(*) from test_run", query_params)
+}
 
-        let query_string = query.sql().unwrap();
+fn page_query(query_params: &TestRunQueryParams) -> QueryBuilder<'static, Postgres> {
+    let per_page = i64::from(query_params.per_page());
+    let offset = i64::from(query_params.page_num() - 1) * per_page;
+
+    let mut query = filtered_query("select * from test_run", query_params);
+    query
+        .push(" order by build_timestamp desc, test_run_id desc limit ")
+        .push_bind(per_page)
+        .push(" offset ")
+        .push_bind(offset);
+    query
+}
+
+impl TestRun {
+    pub(crate) async fn get_by_query_params(
+        query_params: &TestRunQueryParams,
+        conn: &mut PgConnection,
+    ) -> Result<TestRunPage, Error> {
+        query_params.validate()?;
+
+        let mut count_query = count_query(query_params);
+        let mut page_query = page_query(query_params);
 
         info!("Querying DB for test runs");
-        debug!("using SQL command: {}", query_string);
-        Ok(sqlx::query_as(query_string.as_str())
-            .fetch_all(pool)
+        debug!(
+            "using SQL commands: {}; {}",
+            count_query.sql(),
+            page_query.sql()
+        );
+        let total = count_query
+            .build_query_scalar()
+            .fetch_one(&mut *conn)
+            .await?;
+        let test_runs = page_query.build_query_as().fetch_all(conn).await?;
+
+        Ok(TestRunPage { test_runs, total })
+    }
+
+    pub(crate) async fn count(
+        query_params: &TestRunQueryParams,
+        conn: &mut PgConnection,
+    ) -> Result<i64, Error> {
+        query_params.validate()?;
+        Ok(count_query(query_params)
+            .build_query_scalar()
+            .fetch_one(conn)
             .await?)
     }
+
+    /// Keyset pagination over the runs matching `query_params`: up to `limit`
+    /// runs ordered after `after` and before `before`, newest first. With
+    /// `from_end` these are the runs closest to `before` rather than to `after`.
+    pub(crate) async fn get_between(
+        query_params: &TestRunQueryParams,
+        after: Option<TestRunCursor>,
+        before: Option<TestRunCursor>,
+        limit: i64,
+        from_end: bool,
+        conn: &mut PgConnection,
+    ) -> Result<Vec<TestRun>, Error> {
+        query_params.validate()?;
+
+        let mut query = filtered_query("select * from test_run", query_params);
+        if let Some(after) = after {
+            query
+                .push(" and (build_timestamp, test_run_id) < (")
+                .push_bind(after.build_timestamp)
+                .push(", ")
+                .push_bind(after.test_run_id)
+                .push(")");
+        }
+        if let Some(before) = before {
+            query
+                .push(" and (build_timestamp, test_run_id) > (")
+                .push_bind(before.build_timestamp)
+                .push(", ")
+                .push_bind(before.test_run_id)
+                .push(")");
+        }
+        let direction = if from_end { "asc" } else { "desc" };
+        query
+            .push(format!(
+                " order by build_timestamp {direction}, test_run_id {direction} limit "
+            ))
+            .push_bind(limit);
+
+        info!("Querying DB for test runs");
+        debug!("using SQL command: {}", query.sql());
+        let mut test_runs: Vec<TestRun> = query.build_query_as().fetch_all(conn).await?;
+        if from_end {
+            test_runs.reverse();
+        }
+        Ok(test_runs)
+    }
+
+    /// Streams every test run built between `since` and `until`, newest first,
+    /// without buffering the result set.
+    pub(crate) fn stream(
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+        pool: &'static PgPool,
+    ) -> BoxStream<'static, Result<TestRun, Error>> {
+        info!(%since, %until, "Streaming test runs from DB");
+        sqlx::query_as(
+            "select * from test_run where build_timestamp between $1 and $2 \
+             order by build_timestamp desc, test_run_id desc",
+        )
+        .bind(since)
+        .bind(until)
+        .fetch(pool)
+        .map(|row| row.map_err(Error::from))
+        .boxed()
+    }
+
+    pub(crate) async fn get_by_id(
+        test_run_id: Uuid,
+        conn: &mut PgConnection,
+    ) -> Result<TestRun, Error> {
+        info!(%test_run_id, "Querying DB for test run");
+        Ok(
+            sqlx::query_as("select * from test_run where test_run_id = $1")
+                .bind(test_run_id)
+                .fetch_one(conn)
+                .await?,
+        )
+    }
+
+    pub(crate) async fn create(
+        new_test_run: NewTestRun,
+        conn: &mut PgConnection,
+    ) -> Result<TestRun, Error> {
+        new_test_run.validate()?;
+
+        info!(build_number = %new_test_run.build_number, "Inserting test run");
+        Ok(sqlx::query_as(
+            "insert into test_run (build_number, build_url, build_timestamp) \
+             values ($1, $2, coalesce($3, now())) \
+             returning *",
+        )
+        .bind(new_test_run.build_number)
+        .bind(new_test_run.build_url)
+        .bind(new_test_run.build_timestamp)
+        .fetch_one(conn)
+        .await?)
+    }
+
+    /// Records a test run together with all of its results, atomically.
+    pub(crate) async fn create_with_results(
+        new_test_run: NewTestRun,
+        results: &[NewTestResult],
+        conn: &mut PgConnection,
+    ) -> Result<(TestRun, u64), Error> {
+        let mut tx = conn.begin().await?;
+
+        let test_run = Self::create(new_test_run, &mut tx).await?;
+        let mut result_count = 0;
+        for batch in results.chunks(MAX_BATCH_SIZE) {
+            result_count += TestResult::insert_batch(test_run.test_run_id, batch, &mut tx).await?;
+        }
+
+        tx.commit().await?;
+        Ok((test_run, result_count))
+    }
+
+    pub(crate) async fn update(
+        test_run_id: Uuid,
+        patch: TestRunPatch,
+        conn: &mut PgConnection,
+    ) -> Result<TestRun, Error> {
+        patch.validate()?;
+
+        info!(%test_run_id, "Updating test run");
+        Ok(sqlx::query_as(
+            "update test_run set \
+             build_number = coalesce($2, build_number), \
+             build_url = case when $3 then $4 else build_url end, \
+             build_timestamp = coalesce($5, build_timestamp) \
+             where test_run_id = $1 \
+             returning *",
+        )
+        .bind(test_run_id)
+        .bind(patch.build_number)
+        .bind(patch.build_url.is_some())
+        .bind(patch.build_url.flatten())
+        .bind(patch.build_timestamp)
+        .fetch_one(conn)
+        .await?)
+    }
+
+    pub(crate) async fn delete(test_run_id: Uuid, conn: &mut PgConnection) -> Result<(), Error> {
+        info!(%test_run_id, "Deleting test run");
+        let result = sqlx::query("delete from test_run where test_run_id = $1")
+            .bind(test_run_id)
+            .execute(conn)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(sqlx::Error::RowNotFound.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn query_params(build_number: Option<&str>) -> TestRunQueryParams {
+        TestRunQueryParams {
+            test_run_id: Some(Uuid::nil()),
+            build_number: build_number.map(str::to_string),
+            page_num: Some(3),
+            per_page: Some(20),
+            since: Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+            until: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn page_query_binds_every_filter() {
+        assert_eq!(
+            page_query(&query_params(Some("1234"))).sql(),
+            "select * from test_run where build_timestamp between $1 and $2 \
+             and test_run_id = $3 and build_number = $4 \
+             order by build_timestamp desc, test_run_id desc limit $5 offset $6"
+        );
+        assert_eq!(
+            count_query(&query_params(Some("1234"))).sql(),
+            "select count(*) from test_run where build_timestamp between $1 and $2 \
+             and test_run_id = $3 and build_number = $4"
+        );
+    }
+
+    #[test]
+    fn hostile_filters_do_not_change_query_shape() {
+        let benign = page_query(&query_params(Some("1234"))).sql().to_string();
+
+        for hostile in [
+            "1234' or '1'='1",
+            "1234'; drop table test_run; --",
+            "$1) or (1=1",
+            "\\'; select pg_sleep(10); --",
+            "1234\0",
+        ] {
+            let params = query_params(Some(hostile));
+            assert_eq!(page_query(&params).sql(), benign, "input: {hostile}");
+            assert!(!count_query(&params).sql().contains(hostile));
+        }
+    }
 }
@@ -0,0 +1,121 @@
+use crate::error::Error;
+use crate::schema::test_run::TestRun;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{BoxStream, StreamExt};
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::info;
+use utoipa::ToSchema;
+
+/// The channel the `test_run_event` triggers notify with the id of each new
+/// event.
+pub(crate) const CHANNEL: &str = "test_run_events";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum TestRunEventKind {
+    Created,
+    Updated,
+}
+
+impl TestRunEventKind {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            TestRunEventKind::Created => "created",
+            TestRunEventKind::Updated => "updated",
+        }
+    }
+}
+
+/// A change to a test run, along with the run as it was right after the
+/// change.
+#[derive(Clone, Debug, PartialEq, Serialize, sqlx::FromRow, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TestRunEvent {
+    pub(crate) event_id: i64,
+    pub(crate) kind: TestRunEventKind,
+    pub(crate) occurred_at: DateTime<Utc>,
+    /// The transaction that recorded the event.
+    #[serde(skip)]
+    pub(crate) transaction_id: i64,
+    #[sqlx(flatten)]
+    pub(crate) test_run: TestRun,
+}
+
+/// Where an event stands in the order events are published in: by the
+/// transaction that recorded it, then by id.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct EventPosition {
+    transaction_id: i64,
+    event_id: i64,
+}
+
+impl TestRunEvent {
+    pub(crate) fn position(&self) -> EventPosition {
+        EventPosition {
+            transaction_id: self.transaction_id,
+            event_id: self.event_id,
+        }
+    }
+
+    /// The position of events published from now on.
+    pub(crate) async fn published_position(pool: &PgPool) -> Result<EventPosition, Error> {
+        let transaction_id =
+            sqlx::query_scalar("select pg_snapshot_xmin(pg_current_snapshot())::text::int8")
+                .fetch_one(pool)
+                .await?;
+        Ok(EventPosition {
+            transaction_id,
+            event_id: 0,
+        })
+    }
+
+    /// The position of `event_id`, to resume after for a client that last
+    /// saw it. Once the event has been removed there is no telling which of
+    /// the events around it the client has seen, so it has to start over.
+    pub(crate) async fn position_after(
+        event_id: i64,
+        pool: &PgPool,
+    ) -> Result<EventPosition, Error> {
+        let transaction_id = sqlx::query_scalar(
+            "select transaction_id::text::int8 from test_run_event where event_id = $1",
+        )
+        .bind(event_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| {
+            Error::Expired(format!(
+                "Event {event_id} no longer exists, subscribe again without it to receive new events"
+            ))
+        })?;
+        Ok(EventPosition {
+            transaction_id,
+            event_id,
+        })
+    }
+
+    /// Streams every event published after `position`, in order. Events
+    /// recorded by a transaction that began after the oldest one still running
+    /// are held back, since that one may yet record events before them.
+    pub(crate) fn stream_after(
+        position: EventPosition,
+        pool: &'static PgPool,
+    ) -> BoxStream<'static, Result<TestRunEvent, Error>> {
+        info!(?position, "Streaming published test run events from DB");
+        sqlx::query_as(
+            "select event_id, kind, occurred_at, \
+             transaction_id::text::int8 as transaction_id, \
+             test_run_id, build_number, build_url, build_timestamp \
+             from test_run_event \
+             where (transaction_id, event_id) > ($1::text::xid8, $2) \
+             and transaction_id < pg_snapshot_xmin(pg_current_snapshot()) \
+             order by transaction_id, event_id",
+        )
+        .bind(position.transaction_id.to_string())
+        .bind(position.event_id)
+        .fetch(pool)
+        .map(|row| row.map_err(Error::from))
+        .boxed()
+    }
+}
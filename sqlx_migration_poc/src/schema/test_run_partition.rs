@@ -0,0 +1,244 @@
+use crate::config::RetentionAction;
+use chrono::{DateTime, Months, NaiveDate, NaiveTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use tracing::info;
+
+/// Collects the runs to remove, for [`remove_test_runs`].
+const CREATE_EXPIRED: &str = "create temp table expired_test_run on commit drop \
+                              as select * from test_run with no data";
+
+/// A partition of `test_run` holding the runs built in one month (UTC).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestRunPartition {
+    pub(crate) name: String,
+    /// The first day of the month.
+    pub(crate) month: NaiveDate,
+}
+
+/// How many test runs, and results and events of those runs, were removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RemovedTestRuns {
+    pub(crate) test_runs: u64,
+    pub(crate) test_results: u64,
+    pub(crate) test_run_events: u64,
+}
+
+impl TestRunPartition {
+    /// The partition named `test_run_yYYYYmMM`, as created by the
+    /// `create_test_run_partition` function.
+    fn from_name(name: String) -> Option<TestRunPartition> {
+        let (year, month) = name.strip_prefix("test_run_y")?.split_once('m')?;
+        let month = NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)?;
+        Some(TestRunPartition { name, month })
+    }
+
+    /// When the month ends; every run in the partition was built before then.
+    pub(crate) fn end(&self) -> DateTime<Utc> {
+        (self.month + Months::new(1))
+            .and_time(NaiveTime::MIN)
+            .and_utc()
+    }
+
+    /// The monthly partitions, oldest first. Leaves out the default partition.
+    pub(crate) async fn get_all(pool: &PgPool) -> Result<Vec<TestRunPartition>, sqlx::Error> {
+        info!("Querying DB for test run partitions");
+        let names: Vec<String> = sqlx::query_scalar(
+            "select c.relname::text from pg_inherits i \
+             join pg_class c on c.oid = i.inhrelid \
+             where i.inhparent = 'test_run'::regclass \
+             order by c.relname",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(names.into_iter().filter_map(Self::from_name).collect())
+    }
+
+    /// Creates the partition for the month containing `day`, moving the runs
+    /// of that month out of the default partition. Does nothing if the
+    /// partition exists.
+    pub(crate) async fn create(
+        day: NaiveDate,
+        pool: &PgPool,
+    ) -> Result<TestRunPartition, sqlx::Error> {
+        info!(%day, "Creating test run partition");
+        let name = sqlx::query_scalar("select create_test_run_partition($1)")
+            .bind(day)
+            .fetch_one(pool)
+            .await?;
+
+        Self::from_name(name).ok_or_else(|| {
+            sqlx::Error::Protocol("create_test_run_partition returned an unexpected name".into())
+        })
+    }
+
+    /// Drops the partition, first dropping or archiving its runs along with
+    /// their results and events.
+    pub(crate) async fn remove(
+        &self,
+        action: RetentionAction,
+        pool: &PgPool,
+    ) -> Result<RemovedTestRuns, sqlx::Error> {
+        info!(
+            partition = self.name,
+            ?action,
+            "Removing test run partition"
+        );
+        let mut tx = pool.begin().await?;
+        sqlx::query(CREATE_EXPIRED).execute(&mut *tx).await?;
+        sqlx::query(&format!(
+            "insert into expired_test_run select * from \"{}\"",
+            self.name
+        ))
+        .execute(&mut *tx)
+        .await?;
+        let mut removed =
+            remove_results_and_events(&self.name["test_run_".len()..], action, &mut tx).await?;
+
+        // Dropped before the keys of its runs, so that deleting those has no
+        // runs left to cascade to.
+        sqlx::query(&format!(
+            "alter table test_run detach partition \"{}\"",
+            self.name
+        ))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(&format!("drop table \"{}\"", self.name))
+            .execute(&mut *tx)
+            .await?;
+        removed.test_runs = remove_keys(&mut tx).await?;
+
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    /// Drops or archives the runs in the default partition built before
+    /// `before`, along with their results and events.
+    pub(crate) async fn remove_default_before(
+        before: DateTime<Utc>,
+        action: RetentionAction,
+        pool: &PgPool,
+    ) -> Result<RemovedTestRuns, sqlx::Error> {
+        info!(%before, ?action, "Removing old test runs from the default partition");
+        let mut tx = pool.begin().await?;
+        sqlx::query(CREATE_EXPIRED).execute(&mut *tx).await?;
+        sqlx::query(
+            "insert into expired_test_run \
+             select * from test_run_default where build_timestamp < $1",
+        )
+        .bind(before)
+        .execute(&mut *tx)
+        .await?;
+        let mut removed = remove_results_and_events("default", action, &mut tx).await?;
+        sqlx::query(
+            "delete from test_run_default r using expired_test_run e \
+             where r.test_run_id = e.test_run_id",
+        )
+        .execute(&mut *tx)
+        .await?;
+        removed.test_runs = remove_keys(&mut tx).await?;
+
+        tx.commit().await?;
+        Ok(removed)
+    }
+}
+
+/// Removes the results and events of the runs in `expired_test_run`, each in
+/// a single statement. Archived runs, results and events are added to the
+/// `test_run_archive` tables named after `archive_suffix`. The runs are left
+/// for the caller to remove, followed by their keys.
+async fn remove_results_and_events(
+    archive_suffix: &str,
+    action: RetentionAction,
+    conn: &mut PgConnection,
+) -> Result<RemovedTestRuns, sqlx::Error> {
+    if action == RetentionAction::Archive {
+        for statement in [
+            format!(
+                "create table if not exists test_run_archive.test_run_{archive_suffix} \
+                 (like test_run)"
+            ),
+            format!(
+                "insert into test_run_archive.test_run_{archive_suffix} \
+                 select * from expired_test_run"
+            ),
+            format!(
+                "create table if not exists test_run_archive.test_result_{archive_suffix} \
+                 (like test_result)"
+            ),
+            format!(
+                "insert into test_run_archive.test_result_{archive_suffix} \
+                 select r.* from test_result r join expired_test_run using (test_run_id)"
+            ),
+            format!(
+                "create table if not exists test_run_archive.test_run_event_{archive_suffix} \
+                 (like test_run_event)"
+            ),
+            format!(
+                "insert into test_run_archive.test_run_event_{archive_suffix} \
+                 select e.* from test_run_event e join expired_test_run using (test_run_id)"
+            ),
+        ] {
+            sqlx::query(&statement).execute(&mut *conn).await?;
+        }
+    }
+
+    let test_results = sqlx::query(
+        "delete from test_result r using expired_test_run e \
+         where r.test_run_id = e.test_run_id",
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    let test_run_events = sqlx::query(
+        "delete from test_run_event ev using expired_test_run e \
+         where ev.test_run_id = e.test_run_id",
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    Ok(RemovedTestRuns {
+        test_runs: 0,
+        test_results,
+        test_run_events,
+    })
+}
+
+/// Deletes the keys of the runs in `expired_test_run` once the runs and their
+/// results are gone, and returns how many there were. Where the runs were
+/// deleted rather than dropped along with their partition, the deferred
+/// triggers that would delete their keys find nothing left to do.
+async fn remove_keys(conn: &mut PgConnection) -> Result<u64, sqlx::Error> {
+    let removed = sqlx::query(
+        "delete from test_run_key k using expired_test_run e \
+         where k.test_run_id = e.test_run_id",
+    )
+    .execute(conn)
+    .await?;
+    Ok(removed.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parses_monthly_partition_names() {
+        let partition = TestRunPartition::from_name("test_run_y2023m12".to_string()).unwrap();
+        assert_eq!(
+            partition.month,
+            NaiveDate::from_ymd_opt(2023, 12, 1).unwrap()
+        );
+        assert_eq!(
+            partition.end(),
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+        );
+
+        for name in ["test_run_default", "test_run_y2023m13", "test_run_y2023"] {
+            assert_eq!(TestRunPartition::from_name(name.to_string()), None);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use tracing::debug;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TestSuite {
+    pub(crate) test_suite_id: Uuid,
+    pub(crate) name: String,
+}
+
+impl TestSuite {
+    /// Makes sure a suite exists for every name in `names`.
+    pub(crate) async fn ensure_exist(
+        names: &[String],
+        conn: &mut PgConnection,
+    ) -> Result<(), sqlx::Error> {
+        debug!(count = names.len(), "Upserting test suites");
+        sqlx::query(
+            "insert into test_suite (name) \
+             select distinct name from unnest($1::text[]) as s(name) \
+             on conflict (name) do nothing",
+        )
+        .bind(names)
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+}
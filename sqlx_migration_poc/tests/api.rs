@@ -0,0 +1,275 @@
+//! End-to-end tests of the HTTP API against an ephemeral database. Run with
+//! `make test` or `cargo test -- --include-ignored`.
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Method, Request, StatusCode};
+use common::{call, get, get_text, post, send, TestDb};
+use serde_json::json;
+use sqlx_migration_poc::config::Config;
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn manages_test_runs() {
+    let test_db = TestDb::migrated().await;
+    let router = test_db.router();
+
+    let created = post(
+        &router,
+        "/test-runs",
+        json!({"buildNumber": "1", "buildUrl": "https://ci.example.com/1"}),
+    )
+    .await;
+    assert_eq!(created.status, StatusCode::CREATED);
+    let test_run_id = created.body["testRunId"].as_str().unwrap().to_string();
+    let location = format!("/test-runs/{test_run_id}");
+    assert_eq!(created.headers[header::LOCATION], location.as_str());
+
+    let fetched = get(&router, &location).await;
+    assert_eq!(fetched.status, StatusCode::OK);
+    assert_eq!(fetched.body, created.body);
+
+    let updated = send(
+        &router,
+        Method::PATCH,
+        &location,
+        Some(json!({"buildUrl": null})),
+        &[],
+    )
+    .await;
+    assert_eq!(updated.status, StatusCode::OK);
+    assert_eq!(updated.body["buildUrl"], json!(null));
+    assert_eq!(updated.body["buildNumber"], "1");
+
+    let deleted = send(&router, Method::DELETE, &location, None, &[]).await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+    assert_eq!(get(&router, &location).await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn pages_through_test_runs() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let page = get(&router, "/test-runs?per_page=2").await;
+    assert_eq!(page.status, StatusCode::OK);
+    assert_eq!(page.headers["x-total-count"], "3");
+    let builds: Vec<_> = page
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|run| run["buildNumber"].as_str().unwrap())
+        .collect();
+    assert_eq!(builds, ["102", "101"]);
+    let link = page.headers[header::LINK].to_str().unwrap();
+    assert!(link.contains("page_num=2"), "{link}");
+    assert!(link.contains("rel=\"next\""), "{link}");
+
+    let filtered = get(&router, "/test-runs?build_number=100").await;
+    assert_eq!(filtered.body.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn rejects_invalid_test_runs() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let duplicate = post(&router, "/test-runs", json!({"buildNumber": "100"})).await;
+    assert_eq!(duplicate.status, StatusCode::CONFLICT);
+    assert_eq!(
+        duplicate.headers[header::CONTENT_TYPE],
+        "application/problem+json"
+    );
+    assert_eq!(duplicate.body["code"], "conflict");
+    assert_eq!(
+        duplicate.body["message"],
+        "A test run with this build number already exists"
+    );
+
+    let invalid = post(
+        &router,
+        "/test-runs",
+        json!({"buildNumber": " ", "buildUrl": "ftp://example.com"}),
+    )
+    .await;
+    assert_eq!(invalid.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(invalid.body["fieldErrors"].as_array().unwrap().len(), 2);
+
+    let missing = get(&router, "/test-runs/00000000-0000-0000-0000-000000000099").await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn exports_test_runs() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let (status, headers, body) = get_text(&router, "/test-runs/export").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "application/x-ndjson");
+    let build_numbers: Vec<_> = body
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["buildNumber"].clone())
+        .collect();
+    assert_eq!(build_numbers, ["102", "101", "100"]);
+
+    let (status, headers, body) = get_text(&router, "/test-runs/export?format=csv").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+    let mut lines = body.lines();
+    assert_eq!(
+        lines.next(),
+        Some("test_run_id,build_number,build_url,build_timestamp")
+    );
+    assert_eq!(lines.count(), 3);
+
+    let since = (chrono::Utc::now() - chrono::Duration::hours(36))
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let (_, _, body) = get_text(&router, &format!("/test-runs/export?since={since}")).await;
+    assert_eq!(body.lines().count(), 1);
+
+    let invalid = get(&router, "/test-runs/export?format=xml").await;
+    assert_eq!(invalid.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn records_and_lists_results() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+    let uri = "/test-runs/00000000-0000-0000-0000-000000000003/results";
+
+    let submitted = post(
+        &router,
+        uri,
+        json!([
+            {"suiteName": "api", "caseName": "lists", "status": "passed", "durationMs": 5},
+            {"suiteName": "ui", "caseName": "renders", "status": "skipped"}
+        ]),
+    )
+    .await;
+    assert_eq!(submitted.status, StatusCode::OK);
+    assert_eq!(submitted.body["resultCount"], 2);
+
+    let failed = get(&router, &format!("{uri}?status=failed")).await;
+    assert_eq!(failed.status, StatusCode::OK);
+    assert_eq!(failed.body, json!([]));
+
+    let all = get(&router, uri).await;
+    let cases: Vec<_> = all
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| {
+            (
+                r["suiteName"].as_str().unwrap(),
+                r["status"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        cases,
+        [
+            ("api", "passed"),
+            ("api", "passed"),
+            ("api", "passed"),
+            ("ui", "skipped")
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn imports_junit_reports() {
+    let test_db = TestDb::migrated().await;
+    let router = test_db.router();
+
+    let report = r#"<testsuite name="api">
+        <testcase name="creates" time="0.1"/>
+        <testcase name="lists"><failure message="boom"/></testcase>
+    </testsuite>"#;
+    let request = Request::post("/test-runs/import?build_number=200")
+        .header(header::CONTENT_TYPE, "application/xml")
+        .body(Body::from(report))
+        .unwrap();
+
+    let imported = call(&router, request).await;
+    assert_eq!(imported.status, StatusCode::CREATED);
+    assert_eq!(imported.body["resultCount"], 2);
+    assert_eq!(imported.body["testRun"]["buildNumber"], "200");
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn rejects_reports_over_the_size_limit() {
+    let test_db = TestDb::migrated().await;
+    let mut config = Config::default();
+    config.server.max_report_size_mb = 1;
+    let router = test_db.router_with(&config);
+    let limit = 1024 * 1024;
+
+    let import = |size: usize, content_length: Option<usize>| {
+        let mut request = Request::post("/test-runs/import?build_number=200")
+            .header(header::CONTENT_TYPE, "text/x-tap");
+        if let Some(content_length) = content_length {
+            request = request.header(header::CONTENT_LENGTH, content_length);
+        }
+        let mut report = "1..1\nok 1 - fits\n".to_string();
+        report.push_str(&"#".repeat(size - report.len()));
+        request.body(Body::from(report)).unwrap()
+    };
+
+    let declared = call(&router, import(32, Some(limit + 1))).await;
+    assert_eq!(declared.status, StatusCode::PAYLOAD_TOO_LARGE);
+    let streamed = call(&router, import(limit + 1, None)).await;
+    assert_eq!(streamed.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(streamed.body["code"], "invalid_request");
+
+    let at_limit = call(&router, import(limit, None)).await;
+    assert_eq!(at_limit.status, StatusCode::CREATED);
+    assert_eq!(at_limit.body["resultCount"], 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn reports_analytics() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let failure_rates = get(&router, "/analytics/failure-rates").await;
+    assert_eq!(failure_rates.status, StatusCode::OK);
+    assert_eq!(failure_rates.body[0]["caseName"], "lists");
+    assert_eq!(failure_rates.body[0]["failureRate"], 1.0);
+
+    let flaky = get(&router, "/analytics/flaky-tests").await;
+    assert_eq!(flaky.body.as_array().unwrap().len(), 1);
+    assert_eq!(flaky.body[0]["caseName"], "retries");
+    assert_eq!(flaky.body[0]["flips"], 2);
+    assert_eq!(flaky.body[0]["passedAfterRetry"], 1);
+
+    let slowest = get(&router, "/analytics/slowest-tests?limit=1").await;
+    assert_eq!(slowest.body[0]["caseName"], "lists");
+    assert_eq!(slowest.body[0]["maxMs"], 340);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn reports_health_and_migrations() {
+    let test_db = TestDb::migrated().await;
+    let router = test_db.router();
+
+    assert_eq!(get(&router, "/healthz").await.status, StatusCode::OK);
+    let ready = get(&router, "/readyz").await;
+    assert_eq!(ready.status, StatusCode::OK);
+    assert_eq!(ready.body["status"], "ready");
+
+    let migrations = get(&router, "/migrations").await;
+    assert_eq!(migrations.body["upToDate"], true);
+    assert_eq!(migrations.body["pending"], 0);
+}
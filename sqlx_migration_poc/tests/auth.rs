@@ -0,0 +1,121 @@
+//! End-to-end tests of bearer token authentication.
+
+mod common;
+
+use axum::http::{header, HeaderName, Method, StatusCode};
+use common::{send, TestDb};
+use serde_json::json;
+use sqlx::Executor;
+use sqlx_migration_poc::config::{AuthConfig, Config};
+
+const ADMIN_TOKEN: &str = "poc_admin";
+
+fn bearer(token: &str) -> [(HeaderName, String); 1] {
+    [(header::AUTHORIZATION, format!("Bearer {token}"))]
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn requires_tokens_with_matching_scopes() {
+    let test_db = TestDb::migrated().await;
+    test_db
+        .pool
+        .execute(
+            "insert into api_token (name, token_hash, scopes) \
+             values ('bootstrap', sha256('poc_admin'), '{admin}')",
+        )
+        .await
+        .unwrap();
+    let router = test_db.router_with(&Config {
+        auth: AuthConfig { enabled: true },
+        ..Config::default()
+    });
+
+    for uri in ["/healthz", "/openapi.json"] {
+        let open = send(&router, Method::GET, uri, None, &[]).await;
+        assert_eq!(open.status, StatusCode::OK, "{uri}");
+    }
+
+    let anonymous = send(&router, Method::GET, "/test-runs", None, &[]).await;
+    assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(anonymous.headers[header::WWW_AUTHENTICATE], "Bearer");
+    assert_eq!(anonymous.body["code"], "unauthenticated");
+
+    let unknown = send(
+        &router,
+        Method::GET,
+        "/test-runs",
+        None,
+        &bearer("poc_nope"),
+    )
+    .await;
+    assert_eq!(unknown.status, StatusCode::UNAUTHORIZED);
+
+    let admin_only = send(
+        &router,
+        Method::GET,
+        "/test-runs",
+        None,
+        &bearer(ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(admin_only.status, StatusCode::FORBIDDEN);
+    assert_eq!(admin_only.body["code"], "forbidden");
+
+    let issued = send(
+        &router,
+        Method::POST,
+        "/admin/tokens",
+        Some(json!({"name": "ci", "scopes": ["runs:read"]})),
+        &bearer(ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(issued.status, StatusCode::CREATED);
+    assert_eq!(issued.body["scopes"], json!(["runs:read"]));
+    let token = issued.body["token"].as_str().unwrap();
+    let api_token_id = issued.body["apiTokenId"].as_str().unwrap();
+
+    let read = send(&router, Method::GET, "/test-runs", None, &bearer(token)).await;
+    assert_eq!(read.status, StatusCode::OK);
+    let write = send(
+        &router,
+        Method::POST,
+        "/test-runs",
+        Some(json!({"buildNumber": "1"})),
+        &bearer(token),
+    )
+    .await;
+    assert_eq!(write.status, StatusCode::FORBIDDEN);
+    let admin = send(&router, Method::GET, "/admin/tokens", None, &bearer(token)).await;
+    assert_eq!(admin.status, StatusCode::FORBIDDEN);
+
+    let tokens = send(
+        &router,
+        Method::GET,
+        "/admin/tokens",
+        None,
+        &bearer(ADMIN_TOKEN),
+    )
+    .await;
+    let tokens = tokens.body.as_array().unwrap();
+    assert_eq!(tokens.len(), 2);
+    assert!(tokens
+        .iter()
+        .all(|api_token| api_token.get("token").is_none()));
+    assert!(tokens
+        .iter()
+        .all(|api_token| api_token["lastUsedAt"].is_string()));
+
+    let revoke_uri = format!("/admin/tokens/{api_token_id}");
+    let revoked = send(
+        &router,
+        Method::DELETE,
+        &revoke_uri,
+        None,
+        &bearer(ADMIN_TOKEN),
+    )
+    .await;
+    assert_eq!(revoked.status, StatusCode::NO_CONTENT);
+    let read = send(&router, Method::GET, "/test-runs", None, &bearer(token)).await;
+    assert_eq!(read.status, StatusCode::UNAUTHORIZED);
+}
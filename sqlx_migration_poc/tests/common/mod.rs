@@ -0,0 +1,185 @@
+//! Support for tests that need a real database. Each [`TestDb`] is a freshly
+//! created, uniquely named database on the Postgres server described by the
+//! usual `DB_*` variables (falling back to the one from `docker-compose.yml`),
+//! and is dropped again when the `TestDb` goes out of scope.
+#![allow(dead_code)]
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header, HeaderMap, HeaderName, Method, Request, StatusCode};
+use axum::Router;
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
+use sqlx::{Connection, Executor, PgConnection};
+use sqlx_migration_poc::config::{Config, DatabaseConfig};
+use sqlx_migration_poc::create_routes;
+use sqlx_migration_poc::db::{self, connect_options, PgPools};
+use sqlx_migration_poc::repository::Storage;
+use std::env;
+use std::time::Duration;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+const SEED: &str = include_str!("../fixtures/seed.sql");
+
+fn database_config() -> DatabaseConfig {
+    if env::var_os("DB_HOST").is_some() {
+        return Config::from_env().unwrap().database;
+    }
+    DatabaseConfig {
+        host: Some("localhost".to_string()),
+        port: Some(6543),
+        username: Some("admin".to_string()),
+        password: Some("password".to_string()),
+        name: Some("poc".to_string()),
+        ..DatabaseConfig::default()
+    }
+}
+
+pub struct TestDb {
+    pub pool: PgPool,
+    name: String,
+    admin_options: PgConnectOptions,
+}
+
+impl TestDb {
+    /// Creates a database without any tables.
+    pub async fn empty() -> Self {
+        let admin_options = connect_options(&database_config());
+        let name = format!("test_{}", Uuid::new_v4().simple());
+
+        let mut admin = PgConnection::connect_with(&admin_options).await.unwrap();
+        admin
+            .execute(format!("create database {name}").as_str())
+            .await
+            .unwrap();
+        admin.close().await.unwrap();
+
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_with(admin_options.clone().database(&name))
+            .await
+            .unwrap();
+
+        Self {
+            pool,
+            name,
+            admin_options,
+        }
+    }
+
+    /// Creates a database with every embedded migration applied.
+    pub async fn migrated() -> Self {
+        let test_db = Self::empty().await;
+        db::migrate(&test_db.pool, Duration::from_secs(10))
+            .await
+            .unwrap();
+        test_db
+    }
+
+    /// Creates a migrated database holding the runs and results from
+    /// `tests/fixtures/seed.sql`.
+    pub async fn seeded() -> Self {
+        let test_db = Self::migrated().await;
+        test_db.pool.execute(SEED).await.unwrap();
+        test_db
+    }
+
+    /// The application router backed by this database.
+    pub fn router(&self) -> Router {
+        self.router_with(&Config::default())
+    }
+
+    pub fn router_with(&self, config: &Config) -> Router {
+        let pools = Box::leak(Box::new(PgPools::new(self.pool.clone())));
+        create_routes(&Storage::Postgres(pools), config)
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let name = self.name.clone();
+        let admin_options = self.admin_options.clone();
+        // Dropping runs outside of any async context, so the database is
+        // removed from a separate runtime on its own thread.
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async {
+                let mut admin = PgConnection::connect_with(&admin_options).await?;
+                admin
+                    .execute(format!("drop database if exists {name} with (force)").as_str())
+                    .await?;
+                admin.close().await
+            })
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+    }
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+/// Sends `request` through `router` without binding a socket.
+pub async fn call(router: &Router, request: Request<Body>) -> TestResponse {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+
+    TestResponse {
+        status,
+        headers,
+        body,
+    }
+}
+
+pub async fn send(
+    router: &Router,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+    headers: &[(HeaderName, String)],
+) -> TestResponse {
+    let mut request = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    };
+    call(router, request.unwrap()).await
+}
+
+pub async fn get(router: &Router, uri: &str) -> TestResponse {
+    send(router, Method::GET, uri, None, &[]).await
+}
+
+pub async fn post(router: &Router, uri: &str, body: Value) -> TestResponse {
+    send(router, Method::POST, uri, Some(body), &[]).await
+}
+
+/// Like `get`, for endpoints whose body is not a single JSON document.
+pub async fn get_text(router: &Router, uri: &str) -> (StatusCode, HeaderMap, String) {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+    (status, headers, String::from_utf8(bytes.to_vec()).unwrap())
+}
@@ -0,0 +1,230 @@
+//! End-to-end tests of the live test run event streams.
+
+mod common;
+
+use axum::body::{Body, BodyDataStream};
+use axum::http::{Method, Request, StatusCode};
+use axum::Router;
+use common::{post, send, TestDb};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use sqlx_migration_poc::serve;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use tower::ServiceExt;
+
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Reads Server-Sent Events from `body`, skipping keep-alive comments.
+struct EventReader {
+    body: BodyDataStream,
+    buffer: String,
+}
+
+impl EventReader {
+    /// The id, name and data of the next event.
+    async fn next(&mut self) -> (String, String, Value) {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let event: String = self.buffer.drain(..end + 2).collect();
+                let field = |name: &str| {
+                    event
+                        .lines()
+                        .find_map(|line| line.strip_prefix(name))
+                        .map(str::to_string)
+                };
+                if let Some(data) = field("data: ") {
+                    return (
+                        field("id: ").unwrap(),
+                        field("event: ").unwrap(),
+                        serde_json::from_str(&data).unwrap(),
+                    );
+                }
+                continue;
+            }
+            let chunk = timeout(WAIT, self.body.next()).await.unwrap().unwrap();
+            self.buffer
+                .push_str(std::str::from_utf8(&chunk.unwrap()).unwrap());
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn streams_server_sent_events_after_last_event_id() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    // The seed created three runs, so resuming after the first event replays
+    // the other two before any live ones.
+    let request = Request::builder()
+        .uri("/test-runs/events")
+        .header("last-event-id", "1")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut events = EventReader {
+        body: response.into_body().into_data_stream(),
+        buffer: String::new(),
+    };
+
+    let (id, name, event) = events.next().await;
+    assert_eq!((id.as_str(), name.as_str()), ("2", "created"));
+    assert_eq!(event["testRun"]["buildNumber"], "101");
+    let (id, _, event) = events.next().await;
+    assert_eq!(id, "3");
+    assert_eq!(event["testRun"]["buildNumber"], "102");
+
+    let created = post(&router, "/test-runs", json!({"buildNumber": "103"})).await;
+    assert_eq!(created.status, StatusCode::CREATED);
+    let location = format!("/test-runs/{}", created.body["testRunId"].as_str().unwrap());
+    let updated = send(
+        &router,
+        Method::PATCH,
+        &location,
+        Some(json!({"buildUrl": "https://ci.example.com/103"})),
+        &[],
+    )
+    .await;
+    assert_eq!(updated.status, StatusCode::OK);
+
+    let (id, name, event) = events.next().await;
+    assert_eq!((id.as_str(), name.as_str()), ("4", "created"));
+    assert_eq!(event["testRun"], created.body);
+    let (id, name, event) = events.next().await;
+    assert_eq!((id.as_str(), name.as_str()), ("5", "updated"));
+    assert_eq!(event["testRun"], updated.body);
+
+    let rejected = Request::builder()
+        .uri("/test-runs/events")
+        .header("last-event-id", "latest")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(rejected).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let expired = Request::builder()
+        .uri("/test-runs/events")
+        .header("last-event-id", "99")
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(expired).await.unwrap();
+    assert_eq!(response.status(), StatusCode::GONE);
+}
+
+/// Opens an event stream resuming after `last_event_id`.
+async fn resume(router: &Router, last_event_id: &str) -> EventReader {
+    let request = Request::builder()
+        .uri("/test-runs/events")
+        .header("last-event-id", last_event_id)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    EventReader {
+        body: response.into_body().into_data_stream(),
+        buffer: String::new(),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn resumes_without_missing_events_committed_out_of_order() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+    let insert = "insert into test_run (build_number) values ($1)";
+
+    // The first transaction starts first, but records its event after the
+    // second one has, and commits before it.
+    let mut first = test_db.pool.begin().await.unwrap();
+    sqlx::query("select pg_current_xact_id()")
+        .execute(&mut *first)
+        .await
+        .unwrap();
+    let mut second = test_db.pool.begin().await.unwrap();
+    sqlx::query(insert)
+        .bind("201")
+        .execute(&mut *second)
+        .await
+        .unwrap();
+    sqlx::query(insert)
+        .bind("200")
+        .execute(&mut *first)
+        .await
+        .unwrap();
+    first.commit().await.unwrap();
+
+    let mut events = resume(&router, "3").await;
+    let (id, _, event) = events.next().await;
+    assert_eq!(id, "5");
+    assert_eq!(event["testRun"]["buildNumber"], "200");
+    drop(events);
+
+    // Resuming after the newer event still delivers the older one once its
+    // transaction commits.
+    let mut events = resume(&router, "5").await;
+    second.commit().await.unwrap();
+    let (id, _, event) = events.next().await;
+    assert_eq!(id, "4");
+    assert_eq!(event["testRun"]["buildNumber"], "201");
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn sends_events_over_websocket_until_shutdown() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let server = tokio::spawn(serve(
+        listener,
+        router.clone(),
+        async {
+            let _ = shutdown_rx.await;
+        },
+        Duration::from_secs(5),
+    ));
+
+    let url = format!("ws://{addr}/test-runs/events/ws?last_event_id=2");
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let replayed = timeout(WAIT, socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let replayed: Value = serde_json::from_str(replayed.to_text().unwrap()).unwrap();
+    assert_eq!(replayed["eventId"], 3);
+    assert_eq!(replayed["kind"], "created");
+
+    post(&router, "/test-runs", json!({"buildNumber": "103"})).await;
+    let live = timeout(WAIT, socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let live: Value = serde_json::from_str(live.to_text().unwrap()).unwrap();
+    assert_eq!(live["eventId"], 4);
+    assert_eq!(live["testRun"]["buildNumber"], "103");
+
+    shutdown_tx.send(()).unwrap();
+    let Message::Close(Some(frame)) = timeout(WAIT, socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+    else {
+        panic!("expected the server to close the socket");
+    };
+    assert_eq!(frame.code, CloseCode::Away);
+    let _ = socket.close(None).await;
+
+    timeout(WAIT, server).await.unwrap().unwrap().unwrap();
+}
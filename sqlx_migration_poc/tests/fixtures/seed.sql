@@ -0,0 +1,25 @@
+-- Three builds of one suite: 'creates' always passes, 'lists' always fails
+-- and 'retries' flips between outcomes.
+insert into test_run (test_run_id, build_number, build_url, build_timestamp) values
+    ('00000000-0000-0000-0000-000000000001', '100', 'https://ci.example.com/100', now() - interval '3 days'),
+    ('00000000-0000-0000-0000-000000000002', '101', null, now() - interval '2 days'),
+    ('00000000-0000-0000-0000-000000000003', '102', null, now() - interval '1 day');
+
+insert into test_suite (test_suite_id, name) values
+    ('00000000-0000-0000-0000-000000000010', 'api');
+
+insert into test_case (test_case_id, test_suite_id, name) values
+    ('00000000-0000-0000-0000-000000000020', '00000000-0000-0000-0000-000000000010', 'creates'),
+    ('00000000-0000-0000-0000-000000000021', '00000000-0000-0000-0000-000000000010', 'lists'),
+    ('00000000-0000-0000-0000-000000000022', '00000000-0000-0000-0000-000000000010', 'retries');
+
+insert into test_result (test_run_id, test_case_id, status, duration_ms, failure_message, retries) values
+    ('00000000-0000-0000-0000-000000000001', '00000000-0000-0000-0000-000000000020', 'passed', 10, null, 0),
+    ('00000000-0000-0000-0000-000000000002', '00000000-0000-0000-0000-000000000020', 'passed', 12, null, 0),
+    ('00000000-0000-0000-0000-000000000003', '00000000-0000-0000-0000-000000000020', 'passed', 14, null, 0),
+    ('00000000-0000-0000-0000-000000000001', '00000000-0000-0000-0000-000000000021', 'failed', 300, 'expected 200, got 500', 0),
+    ('00000000-0000-0000-0000-000000000002', '00000000-0000-0000-0000-000000000021', 'failed', 320, 'expected 200, got 500', 0),
+    ('00000000-0000-0000-0000-000000000003', '00000000-0000-0000-0000-000000000021', 'failed', 340, 'expected 200, got 500', 0),
+    ('00000000-0000-0000-0000-000000000001', '00000000-0000-0000-0000-000000000022', 'passed', 50, null, 1),
+    ('00000000-0000-0000-0000-000000000002', '00000000-0000-0000-0000-000000000022', 'failed', 60, 'timed out', 0),
+    ('00000000-0000-0000-0000-000000000003', '00000000-0000-0000-0000-000000000022', 'passed', 55, null, 0);
@@ -0,0 +1,93 @@
+use axum::routing::get;
+use axum::Router;
+use sqlx_migration_poc::serve;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+async fn slow(delay: Duration) -> &'static str {
+    tokio::time::sleep(delay).await;
+    "done"
+}
+
+/// Starts a server whose `/slow` route takes `delay` to respond.
+async fn start(
+    delay: Duration,
+    drain_timeout: Duration,
+) -> (
+    SocketAddr,
+    oneshot::Sender<()>,
+    JoinHandle<std::io::Result<()>>,
+) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = Router::new().route("/slow", get(move || slow(delay)));
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let server = tokio::spawn(serve(
+        listener,
+        router,
+        async {
+            let _ = shutdown_rx.await;
+        },
+        drain_timeout,
+    ));
+
+    (addr, shutdown_tx, server)
+}
+
+async fn send_request(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    stream
+}
+
+#[tokio::test]
+async fn finishes_in_flight_requests_before_stopping() {
+    let (addr, shutdown, server) = start(Duration::from_millis(500), Duration::from_secs(5)).await;
+
+    let mut stream = send_request(addr).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown.send(()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    assert!(response.ends_with("done"), "{response}");
+
+    tokio::time::timeout(Duration::from_secs(1), server)
+        .await
+        .expect("server did not stop after draining")
+        .unwrap()
+        .unwrap();
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn gives_up_on_requests_after_drain_timeout() {
+    let (addr, shutdown, server) = start(Duration::from_secs(60), Duration::from_millis(200)).await;
+
+    let mut stream = send_request(addr).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let started = Instant::now();
+    shutdown.send(()).unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("server did not stop after the drain timeout")
+        .unwrap()
+        .unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 503 Service Unavailable"),
+        "{response}"
+    );
+}
@@ -0,0 +1,135 @@
+//! End-to-end tests of the GraphQL endpoint against the seeded database.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::Router;
+use common::{post, TestDb};
+use serde_json::{json, Value};
+
+async fn query(router: &Router, query: &str, variables: Value) -> Value {
+    let response = post(
+        router,
+        "/graphql",
+        json!({"query": query, "variables": variables}),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK);
+    response.body
+}
+
+const TEST_RUNS: &str = "query($after: String) {
+    testRuns(first: 2, after: $after) {
+        totalCount
+        pageInfo { hasNextPage hasPreviousPage endCursor }
+        edges {
+            node {
+                buildNumber
+                results(status: FAILED) { caseName failureMessage }
+            }
+        }
+    }
+}";
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn pages_through_test_runs_with_their_results() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let first = query(&router, TEST_RUNS, json!({})).await;
+    let test_runs = &first["data"]["testRuns"];
+    assert_eq!(test_runs["totalCount"], 3);
+    assert_eq!(test_runs["pageInfo"]["hasNextPage"], true);
+    assert_eq!(test_runs["pageInfo"]["hasPreviousPage"], false);
+    assert_eq!(
+        test_runs["edges"],
+        json!([
+            {"node": {"buildNumber": "102", "results": [
+                {"caseName": "lists", "failureMessage": "expected 200, got 500"},
+            ]}},
+            {"node": {"buildNumber": "101", "results": [
+                {"caseName": "lists", "failureMessage": "expected 200, got 500"},
+                {"caseName": "retries", "failureMessage": "timed out"},
+            ]}},
+        ])
+    );
+
+    let after = test_runs["pageInfo"]["endCursor"].clone();
+    let second = query(&router, TEST_RUNS, json!({"after": after})).await;
+    let test_runs = &second["data"]["testRuns"];
+    assert_eq!(test_runs["pageInfo"]["hasNextPage"], false);
+    assert_eq!(test_runs["pageInfo"]["hasPreviousPage"], true);
+    assert_eq!(test_runs["edges"][0]["node"]["buildNumber"], "100");
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn filters_results_of_a_run() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let body = query(
+        &router,
+        "{ testRun(testRunId: \"00000000-0000-0000-0000-000000000002\") {
+            failed: results(status: FAILED) { caseName }
+            api: results(suiteName: \"api\") { caseName }
+            other: results(suiteName: \"other\") { caseName }
+        } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(
+        body["data"]["testRun"],
+        json!({
+            "failed": [{"caseName": "lists"}, {"caseName": "retries"}],
+            "api": [{"caseName": "creates"}, {"caseName": "lists"}, {"caseName": "retries"}],
+            "other": [],
+        })
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn rejects_queries_that_are_too_complex() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let body = query(
+        &router,
+        "{ testRuns(first: 500) { edges { node { results { caseName } } } } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(body["data"], Value::Null);
+    assert_eq!(body["errors"][0]["message"], "Query is too complex.");
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn reports_errors_with_codes() {
+    let test_db = TestDb::seeded().await;
+    let router = test_db.router();
+
+    let body = query(
+        &router,
+        "{ testRuns(last: 1000) { totalCount } flakyTests { caseName } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(
+        body["data"],
+        json!({"flakyTests": [{"caseName": "retries"}]})
+    );
+    let error = &body["errors"][0];
+    assert_eq!(error["extensions"]["code"], "validation_failed");
+    assert_eq!(error["extensions"]["fieldErrors"][0]["field"], "last");
+
+    let body = query(
+        &router,
+        "{ testRun(testRunId: \"00000000-0000-0000-0000-000000000009\") { buildNumber } }",
+        json!({}),
+    )
+    .await;
+    assert_eq!(body, json!({"data": {"testRun": null}}));
+}
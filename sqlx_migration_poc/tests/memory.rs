@@ -0,0 +1,85 @@
+//! End-to-end tests of the test run endpoints on the in-memory backend.
+
+mod common;
+
+use axum::http::{header, Method, StatusCode};
+use axum::Router;
+use common::{get, post, send};
+use serde_json::json;
+use sqlx_migration_poc::config::Config;
+use sqlx_migration_poc::create_routes;
+use sqlx_migration_poc::repository::Storage;
+
+fn router() -> Router {
+    create_routes(&Storage::Memory(Default::default()), &Config::default())
+}
+
+#[tokio::test]
+async fn crud_round_trip() {
+    let router = router();
+
+    let created = post(&router, "/test-runs", json!({ "buildNumber": "42" })).await;
+    assert_eq!(created.status, StatusCode::CREATED);
+    let location = created.headers[header::LOCATION]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        location,
+        format!("/test-runs/{}", created.body["testRunId"].as_str().unwrap())
+    );
+
+    let fetched = get(&router, &location).await;
+    assert_eq!(fetched.status, StatusCode::OK);
+    assert_eq!(fetched.body, created.body);
+
+    let updated = send(
+        &router,
+        Method::PATCH,
+        &location,
+        Some(json!({ "buildUrl": "https://ci.example.com/42" })),
+        &[],
+    )
+    .await;
+    assert_eq!(updated.status, StatusCode::OK);
+    assert_eq!(updated.body["buildUrl"], "https://ci.example.com/42");
+
+    let deleted = send(&router, Method::DELETE, &location, None, &[]).await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+    assert_eq!(get(&router, &location).await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn lists_with_pagination_headers() {
+    let router = router();
+    for build_number in 1..=3 {
+        let body = json!({ "buildNumber": build_number.to_string() });
+        post(&router, "/test-runs", body).await;
+    }
+
+    let listed = get(&router, "/test-runs?per_page=2").await;
+    assert_eq!(listed.status, StatusCode::OK);
+    assert_eq!(listed.headers["x-total-count"], "3");
+    let link = listed.headers[header::LINK].to_str().unwrap();
+    assert!(link.contains("rel=\"next\""), "{link}");
+    assert_eq!(listed.body.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn rejects_duplicate_and_invalid_runs() {
+    let router = router();
+    let body = json!({ "buildNumber": "7" });
+    post(&router, "/test-runs", body.clone()).await;
+
+    let duplicate = post(&router, "/test-runs", body).await;
+    assert_eq!(duplicate.status, StatusCode::CONFLICT);
+    assert_eq!(duplicate.body["code"], "conflict");
+    assert_eq!(
+        duplicate.body["message"],
+        "A test run with this build number already exists"
+    );
+
+    let invalid = post(&router, "/test-runs", json!({ "buildNumber": "" })).await;
+    assert_eq!(invalid.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(invalid.body["fieldErrors"][0]["field"], "buildNumber");
+}
@@ -0,0 +1,91 @@
+//! Checks that every down migration exactly undoes its up migration.
+//!
+//! Needs a Postgres server, see `tests/common`. Run with
+//! `cargo test --test migrations -- --ignored`.
+
+mod common;
+
+use common::TestDb;
+use sqlx::migrate::{Migrate, Migration};
+use sqlx::postgres::PgConnection;
+use sqlx_migration_poc::db::MIGRATOR;
+
+/// Describes every table, column, constraint, index, function and trigger in
+/// the `public` schema, leaving out sqlx's own bookkeeping table.
+async fn dump_schema(conn: &mut PgConnection) -> Vec<String> {
+    sqlx::query_scalar(
+        "select format('column %s.%s %s null=%s default=%s', \
+         table_name, column_name, data_type, is_nullable, column_default) \
+         from information_schema.columns \
+         where table_schema = 'public' and table_name <> '_sqlx_migrations' \
+         union all \
+         select format('constraint %s.%s %s', conrelid::regclass, conname, \
+         pg_get_constraintdef(oid)) \
+         from pg_constraint \
+         where connamespace = 'public'::regnamespace \
+         and conrelid <> coalesce(to_regclass('_sqlx_migrations'), 0) \
+         union all \
+         select format('index %s', indexdef) from pg_indexes \
+         where schemaname = 'public' and tablename <> '_sqlx_migrations' \
+         union all \
+         select format('function %s', pg_get_functiondef(p.oid)) from pg_proc p \
+         where pronamespace = 'public'::regnamespace \
+         union all \
+         select format('trigger %s', pg_get_triggerdef(oid)) from pg_trigger \
+         where not tgisinternal \
+         order by 1",
+    )
+    .fetch_all(conn)
+    .await
+    .unwrap()
+}
+
+fn down_migration(up: &Migration) -> &'static Migration {
+    MIGRATOR
+        .iter()
+        .find(|m| m.version == up.version && m.migration_type.is_down_migration())
+        .unwrap_or_else(|| panic!("migration {} has no down script", up.version))
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn down_migrations_undo_up_migrations() {
+    let test_db = TestDb::empty().await;
+    let mut conn = test_db.pool.acquire().await.unwrap();
+    conn.ensure_migrations_table().await.unwrap();
+    let empty = dump_schema(&mut conn).await;
+
+    let ups: Vec<_> = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .collect();
+    assert!(!ups.is_empty());
+
+    for up in &ups {
+        let before = dump_schema(&mut conn).await;
+        conn.apply(up).await.unwrap();
+        let after = dump_schema(&mut conn).await;
+        assert_ne!(before, after, "migration {} changed nothing", up.version);
+
+        conn.revert(down_migration(up)).await.unwrap();
+        assert_eq!(
+            dump_schema(&mut conn).await,
+            before,
+            "reverting migration {} left the schema changed",
+            up.version
+        );
+
+        conn.apply(up).await.unwrap();
+        assert_eq!(
+            dump_schema(&mut conn).await,
+            after,
+            "reapplying migration {} produced a different schema",
+            up.version
+        );
+    }
+
+    for up in ups.iter().rev() {
+        conn.revert(down_migration(up)).await.unwrap();
+    }
+    assert_eq!(dump_schema(&mut conn).await, empty);
+}
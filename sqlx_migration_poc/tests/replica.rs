@@ -0,0 +1,140 @@
+//! End-to-end tests of routing reads to a read replica. A second test
+//! database stands in for the replica, so reads and writes can be told apart
+//! by which database they hit.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::Router;
+use common::{get, get_text, post, TestDb};
+use serde_json::json;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Executor, PgPool};
+use sqlx_migration_poc::config::Config;
+use sqlx_migration_poc::create_routes;
+use sqlx_migration_poc::db::PgPools;
+use sqlx_migration_poc::repository::Storage;
+use std::time::Duration;
+
+async fn router(primary: &TestDb, replica: PgPool) -> Router {
+    let pools =
+        PgPools::with_replica(primary.pool.clone(), replica, Duration::from_secs(1), None).await;
+    let pools = Box::leak(Box::new(pools));
+    create_routes(&Storage::Postgres(pools), &Config::default())
+}
+
+async fn count_runs(test_db: &TestDb) -> i64 {
+    sqlx::query_scalar("select count(*) from test_run")
+        .fetch_one(&test_db.pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn reads_from_the_replica_and_writes_to_the_primary() {
+    let primary = TestDb::migrated().await;
+    let replica = TestDb::seeded().await;
+    let router = router(&primary, replica.pool.clone()).await;
+
+    let created = post(&router, "/test-runs", json!({"buildNumber": "200"})).await;
+    assert_eq!(created.status, StatusCode::CREATED);
+    assert_eq!(count_runs(&primary).await, 1);
+    assert_eq!(count_runs(&replica).await, 3);
+
+    let listed = get(&router, "/test-runs").await;
+    assert_eq!(listed.status, StatusCode::OK);
+    assert_eq!(listed.headers["x-total-count"], "3");
+    let analytics = get(&router, "/analytics/flaky-tests").await;
+    assert_eq!(analytics.body[0]["caseName"], "retries");
+
+    let (_, _, metrics) = get_text(&router, "/metrics").await;
+    for pool in ["primary", "replica"] {
+        let gauge = format!("db_pool_max_connections{{pool=\"{pool}\"}}");
+        assert!(metrics.contains(&gauge), "{metrics}");
+        // The create waited on the primary, the reads on the replica.
+        let waits = format!("db_pool_acquire_wait_seconds_count{{pool=\"{pool}\"}}");
+        assert!(metrics.contains(&waits), "{metrics}");
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn falls_back_to_the_primary_when_the_replica_is_down() {
+    let primary = TestDb::seeded().await;
+    let unreachable = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_millis(500))
+        .connect_lazy_with(PgConnectOptions::new().host("127.0.0.1").port(1));
+    let router = router(&primary, unreachable).await;
+
+    let listed = get(&router, "/test-runs").await;
+    assert_eq!(listed.status, StatusCode::OK);
+    assert_eq!(listed.headers["x-total-count"], "3");
+}
+
+/// Stand-ins for the catalog that make a server look like a standby whose WAL
+/// receiver has stopped, for connections that search them first.
+const STOPPED_STANDBY: &str = "create schema standby; \
+     create function standby.pg_is_in_recovery() returns bool language sql as 'select true'; \
+     create view standby.pg_stat_wal_receiver as select 'stopping'::text as status";
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn stops_reading_from_a_replica_that_is_not_streaming() {
+    let primary = TestDb::migrated().await;
+    let replica = TestDb::seeded().await;
+    replica.pool.execute(STOPPED_STANDBY).await.unwrap();
+    let options = (*replica.pool.connect_options())
+        .clone()
+        .options([("search_path", "standby,pg_catalog,public")]);
+    let replica_pool = PgPoolOptions::new().connect_with(options).await.unwrap();
+    // Without a lag limit, only the WAL receiver tells it apart from a
+    // healthy replica.
+    let router = router(&primary, replica_pool).await;
+
+    let listed = get(&router, "/test-runs").await;
+    assert_eq!(listed.status, StatusCode::OK);
+    assert_eq!(listed.headers["x-total-count"], "0");
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn retries_reads_on_the_primary_when_the_replica_connection_fails() {
+    let primary = TestDb::migrated().await;
+    let replica = TestDb::seeded().await;
+    // A single connection that is not checked before use, so the next read
+    // finds it cut, and health checks too far apart to notice first.
+    let replica_pool = PgPoolOptions::new()
+        .max_connections(1)
+        .test_before_acquire(false)
+        .connect_with((*replica.pool.connect_options()).clone())
+        .await
+        .unwrap();
+    let pools = PgPools::with_replica(
+        primary.pool.clone(),
+        replica_pool,
+        Duration::from_secs(60),
+        None,
+    )
+    .await;
+    let router = create_routes(
+        &Storage::Postgres(Box::leak(Box::new(pools))),
+        &Config::default(),
+    );
+    assert_eq!(
+        get(&router, "/test-runs").await.headers["x-total-count"],
+        "3"
+    );
+
+    sqlx::query(
+        "select pg_terminate_backend(pid) from pg_stat_activity \
+         where datname = current_database() and pid <> pg_backend_pid()",
+    )
+    .execute(&replica.pool)
+    .await
+    .unwrap();
+
+    let listed = get(&router, "/test-runs").await;
+    assert_eq!(listed.status, StatusCode::OK);
+    assert_eq!(listed.headers["x-total-count"], "0");
+}
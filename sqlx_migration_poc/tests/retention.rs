@@ -0,0 +1,226 @@
+//! End-to-end tests of the monthly test run partitions and their retention.
+
+mod common;
+
+use common::TestDb;
+use sqlx::{Executor, PgPool};
+use sqlx_migration_poc::config::{RetentionAction, RetentionConfig};
+use sqlx_migration_poc::retention;
+
+/// A run built in January 2020, which gets a partition of its own, and one
+/// built in February 2020, which is left in the default partition. Each has
+/// one result, and an event from being created.
+const OLD_RUNS: &str = r#"
+select create_test_run_partition('2020-01-01');
+
+insert into test_run (test_run_id, build_number, build_timestamp) values
+    ('00000000-0000-0000-0000-000000000098', '98', '2020-01-15T12:00:00Z'),
+    ('00000000-0000-0000-0000-000000000099', '99', '2020-02-15T12:00:00Z');
+
+insert into test_result (test_run_id, test_case_id, status) values
+    ('00000000-0000-0000-0000-000000000098', '00000000-0000-0000-0000-000000000020', 'passed'),
+    ('00000000-0000-0000-0000-000000000099', '00000000-0000-0000-0000-000000000020', 'failed');
+"#;
+
+async fn seeded_with_old_runs() -> TestDb {
+    let test_db = TestDb::seeded().await;
+    test_db.pool.execute(OLD_RUNS).await.unwrap();
+    test_db
+}
+
+async fn count(sql: &str, pool: &PgPool) -> i64 {
+    sqlx::query_scalar(sql).fetch_one(pool).await.unwrap()
+}
+
+async fn partitions(pool: &PgPool) -> Vec<String> {
+    sqlx::query_scalar(
+        "select c.relname::text from pg_inherits i join pg_class c on c.oid = i.inhrelid \
+         where i.inhparent = 'test_run'::regclass order by 1",
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn creates_upcoming_partitions_and_keeps_runs_without_max_age() {
+    let test_db = seeded_with_old_runs().await;
+    let pool = &test_db.pool;
+
+    retention::enforce(pool, &RetentionConfig::default())
+        .await
+        .unwrap();
+    retention::enforce(pool, &RetentionConfig::default())
+        .await
+        .unwrap();
+
+    let partitions = partitions(pool).await;
+    assert_eq!(partitions.len(), 4, "{partitions:?}");
+    assert!(partitions.contains(&"test_run_y2020m01".to_string()));
+    assert_eq!(count("select count(*) from test_run", pool).await, 5);
+    assert_eq!(count("select count(*) from test_result", pool).await, 11);
+
+    // Updates moving a run to another month keep its results.
+    sqlx::query("update test_run set build_timestamp = now() where build_number = '98'")
+        .execute(pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        count(
+            "select count(*) from test_result \
+             where test_run_id = '00000000-0000-0000-0000-000000000098'",
+            pool
+        )
+        .await,
+        1
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn keeps_one_key_per_run_across_partitions() {
+    let test_db = seeded_with_old_runs().await;
+    let pool = &test_db.pool;
+
+    // Moving a run to the default partition, and back, keeps its key in step.
+    for (build_number, build_timestamp) in [("98a", "2020-03-15"), ("98b", "2020-01-20")] {
+        sqlx::query(
+            "update test_run set build_number = $1, build_timestamp = $2::timestamptz \
+             where test_run_id = '00000000-0000-0000-0000-000000000098'",
+        )
+        .bind(build_number)
+        .bind(build_timestamp)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+    let key: String = sqlx::query_scalar(
+        "select build_number from test_run_key \
+         where test_run_id = '00000000-0000-0000-0000-000000000098'",
+    )
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    assert_eq!(key, "98b");
+    assert_eq!(count("select count(*) from test_run_key", pool).await, 5);
+
+    // A new run cannot take over the id of another, and its results.
+    let duplicate = sqlx::query(
+        "insert into test_run (test_run_id, build_number) \
+         values ('00000000-0000-0000-0000-000000000099', '97')",
+    )
+    .execute(pool)
+    .await
+    .unwrap_err();
+    assert_eq!(
+        duplicate.as_database_error().unwrap().constraint(),
+        Some("test_run_key_pkey")
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn drops_expired_runs_and_their_results() {
+    let test_db = seeded_with_old_runs().await;
+    let pool = &test_db.pool;
+    let config = RetentionConfig {
+        max_age_days: Some(30),
+        ..RetentionConfig::default()
+    };
+
+    retention::enforce(pool, &config).await.unwrap();
+
+    assert!(!partitions(pool)
+        .await
+        .contains(&"test_run_y2020m01".to_string()));
+    assert_eq!(count("select count(*) from test_run", pool).await, 3);
+    assert_eq!(count("select count(*) from test_run_key", pool).await, 3);
+    assert_eq!(count("select count(*) from test_result", pool).await, 9);
+    assert_eq!(count("select count(*) from test_run_event", pool).await, 3);
+    assert_eq!(
+        count(
+            "select count(*) from pg_tables where schemaname = 'test_run_archive'",
+            pool
+        )
+        .await,
+        0
+    );
+
+    // The build numbers of removed runs can be used again.
+    sqlx::query("insert into test_run (build_number) values ('98'), ('99')")
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn archives_expired_runs_and_their_results() {
+    let test_db = seeded_with_old_runs().await;
+    let pool = &test_db.pool;
+    let config = RetentionConfig {
+        max_age_days: Some(30),
+        action: RetentionAction::Archive,
+        ..RetentionConfig::default()
+    };
+
+    retention::enforce(pool, &config).await.unwrap();
+
+    assert!(!partitions(pool)
+        .await
+        .contains(&"test_run_y2020m01".to_string()));
+    assert_eq!(count("select count(*) from test_run", pool).await, 3);
+    assert_eq!(count("select count(*) from test_result", pool).await, 9);
+    for (table, build_number) in [
+        ("test_run_archive.test_run_y2020m01", "98"),
+        ("test_run_archive.test_run_default", "99"),
+    ] {
+        let archived: Vec<String> =
+            sqlx::query_scalar(&format!("select build_number from {table}"))
+                .fetch_all(pool)
+                .await
+                .unwrap();
+        assert_eq!(archived, [build_number], "{table}");
+    }
+    assert_eq!(count("select count(*) from test_run_event", pool).await, 3);
+    for table in [
+        "test_run_archive.test_result_y2020m01",
+        "test_run_archive.test_result_default",
+        "test_run_archive.test_run_event_y2020m01",
+        "test_run_archive.test_run_event_default",
+    ] {
+        assert_eq!(
+            count(&format!("select count(*) from {table}"), pool).await,
+            1
+        );
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server"]
+async fn leaves_runs_alone_while_another_instance_holds_the_lock() {
+    let test_db = seeded_with_old_runs().await;
+    let pool = &test_db.pool;
+    let config = RetentionConfig {
+        max_age_days: Some(30),
+        ..RetentionConfig::default()
+    };
+
+    let mut holder = pool.acquire().await.unwrap();
+    sqlx::query("select pg_advisory_lock($1)")
+        .bind(retention::LOCK_ID)
+        .execute(&mut *holder)
+        .await
+        .unwrap();
+    retention::enforce(pool, &config).await.unwrap();
+    assert_eq!(count("select count(*) from test_run", pool).await, 5);
+
+    sqlx::query("select pg_advisory_unlock($1)")
+        .bind(retention::LOCK_ID)
+        .execute(&mut *holder)
+        .await
+        .unwrap();
+    retention::enforce(pool, &config).await.unwrap();
+    assert_eq!(count("select count(*) from test_run", pool).await, 3);
+}
@@ -0,0 +1,134 @@
+//! End-to-end tests of the test run endpoints on the SQLite backend, which
+//! needs nothing but a scratch file.
+
+mod common;
+
+use axum::http::{header, Method, StatusCode};
+use axum::Router;
+use common::{get, post, send};
+use serde_json::json;
+use sqlx_migration_poc::config::{Config, DatabaseConfig};
+use sqlx_migration_poc::create_routes;
+use sqlx_migration_poc::db::init_sqlite_pool;
+use sqlx_migration_poc::repository::Storage;
+use std::path::PathBuf;
+use std::{env, fs};
+use uuid::Uuid;
+
+/// A SQLite database file that is deleted on drop.
+struct TestFile(PathBuf);
+
+impl Drop for TestFile {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+async fn router() -> (TestFile, Router) {
+    let file = TestFile(env::temp_dir().join(format!("test_{}.db", Uuid::new_v4().simple())));
+    let pool = init_sqlite_pool(&file.0, &DatabaseConfig::default())
+        .await
+        .unwrap();
+    (
+        file,
+        create_routes(&Storage::Sqlite(pool), &Config::default()),
+    )
+}
+
+#[tokio::test]
+async fn manages_test_runs() {
+    let (_file, router) = router().await;
+
+    let created = post(
+        &router,
+        "/test-runs",
+        json!({"buildNumber": "1", "buildUrl": "https://ci.example.com/1"}),
+    )
+    .await;
+    assert_eq!(created.status, StatusCode::CREATED);
+    let location = created.headers[header::LOCATION]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let fetched = get(&router, &location).await;
+    assert_eq!(fetched.status, StatusCode::OK);
+    assert_eq!(fetched.body, created.body);
+
+    let updated = send(
+        &router,
+        Method::PATCH,
+        &location,
+        Some(json!({"buildUrl": null, "buildTimestamp": "2024-01-01T00:00:00Z"})),
+        &[],
+    )
+    .await;
+    assert_eq!(updated.status, StatusCode::OK);
+    assert_eq!(updated.body["buildUrl"], json!(null));
+    assert_eq!(updated.body["buildTimestamp"], "2024-01-01T00:00:00Z");
+
+    let duplicate = post(&router, "/test-runs", json!({"buildNumber": "1"})).await;
+    assert_eq!(duplicate.status, StatusCode::CONFLICT);
+    assert_eq!(
+        duplicate.body["message"],
+        "A test run with this build number already exists"
+    );
+
+    let deleted = send(&router, Method::DELETE, &location, None, &[]).await;
+    assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+    assert_eq!(get(&router, &location).await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn filters_and_pages_like_postgres() {
+    let (_file, router) = router().await;
+
+    for (build_number, day) in [("100", 1), ("101", 2), ("102", 3), ("103", 4)] {
+        let created = post(
+            &router,
+            "/test-runs",
+            json!({
+                "buildNumber": build_number,
+                "buildTimestamp": format!("2024-03-0{day}T12:00:00.5Z"),
+            }),
+        )
+        .await;
+        assert_eq!(created.status, StatusCode::CREATED);
+    }
+
+    let page = get(
+        &router,
+        "/test-runs?per_page=2&page_num=2&since=2024-03-01T12:00:00.5Z&until=2024-03-03T23:00:00Z",
+    )
+    .await;
+    assert_eq!(page.status, StatusCode::OK);
+    assert_eq!(page.headers["x-total-count"], "3");
+    let builds: Vec<_> = page
+        .body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|run| run["buildNumber"].as_str().unwrap())
+        .collect();
+    assert_eq!(builds, ["100"]);
+
+    let filtered = get(
+        &router,
+        "/test-runs?build_number=103&since=2024-01-01T00:00:00Z",
+    )
+    .await;
+    assert_eq!(filtered.body.as_array().unwrap().len(), 1);
+
+    let invalid = get(&router, "/test-runs?per_page=0").await;
+    assert_eq!(invalid.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    assert_eq!(get(&router, "/readyz").await.status, StatusCode::OK);
+    assert_eq!(
+        get(&router, "/analytics/failure-rates").await.status,
+        StatusCode::NOT_FOUND
+    );
+}